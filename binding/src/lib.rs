@@ -1,4 +1,4 @@
-use graph::{self, generator, subgraph};
+use graph::{self, generator, layout::LayoutOptions, parse_error::ParseError, subgraph};
 use log::info;
 use ouroboros::self_referencing;
 use wasm_bindgen::prelude::*;
@@ -11,6 +11,8 @@ extern "C" {
 #[wasm_bindgen]
 pub struct Graph {
     holder: Holder,
+    /// Graph attributes set from the page, they override the input ones.
+    attributes: Vec<(String, String)>,
 }
 
 #[self_referencing]
@@ -18,7 +20,7 @@ struct Holder {
     input: String,
     #[borrows(input)]
     #[covariant]
-    graph: Result<graph::read_dot::DotGraph<'this>, String>,
+    graph: Result<graph::read_dot::DotGraph<'this>, Vec<ParseError>>,
 }
 
 #[wasm_bindgen]
@@ -38,6 +40,53 @@ impl SearchResultItem {
     }
 }
 
+/// Parse error position in UTF-16 code units to select it in the editor.
+#[wasm_bindgen]
+pub struct ParseErrorItem {
+    line: usize,
+    column: usize,
+    start: usize,
+    end: usize,
+    message: String,
+}
+
+#[wasm_bindgen]
+impl ParseErrorItem {
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn message(&self) -> JsValue {
+        self.message.clone().into()
+    }
+}
+
+fn errors_to_string(errors: &[ParseError]) -> String {
+    errors
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
 #[wasm_bindgen]
 impl Graph {
     pub fn new(dot: &JsValue) -> Self {
@@ -56,17 +105,24 @@ impl Graph {
                             graph::to_dag::to_dag(&mut g.graph);
                             g
                         })
-                        .inspect_err(|e| log::error!("Parse failed: {e}"))
+                        .inspect_err(|e| log::error!("Parse failed: {}", errors_to_string(e)))
                 },
             }
             .build(),
+            attributes: vec![],
         }
     }
 
+    /// Sets a layout graph attribute like `nodesep`, see
+    /// [`graph::layout::LayoutOptions::set`].
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        self.attributes.push((name.to_string(), value.to_string()));
+    }
+
     pub fn node_count(&self) -> JsValue {
         match &self.holder.borrow_graph() {
             Ok(g) => g.graph.nodes_count().into(),
-            Err(e) => e.into(),
+            Err(e) => errors_to_string(e).into(),
         }
     }
 
@@ -74,24 +130,51 @@ impl Graph {
         self.holder.borrow_graph().is_err().into()
     }
 
+    pub fn errors(&self) -> JsValue {
+        let input = self.holder.borrow_input();
+        let utf16_offset = |n: usize| input[..n].encode_utf16().count();
+        (match self.holder.borrow_graph() {
+            Err(errors) => errors
+                .iter()
+                .map(|e| {
+                    let span = e.span();
+                    ParseErrorItem {
+                        line: span.line,
+                        column: span.column,
+                        start: utf16_offset(span.start),
+                        end: utf16_offset(span.end),
+                        message: e.to_string(),
+                    }
+                })
+                .map(JsValue::from)
+                .collect::<js_sys::Array>(),
+            Ok(_) => js_sys::Array::new(),
+        })
+        .into()
+    }
+
     pub fn render(&self, around_node_id: &str, max_nodes: u32, max_edges: u32) -> JsValue {
         match &self.holder.borrow_graph() {
-            Err(e) => (String::from("<pre>") + &e + "</pre>").into(),
+            Err(e) => format!("<pre>{}</pre>", escape_html(&errors_to_string(e))).into(),
             Ok(dot) => {
                 if dot.graph.nodes_count() == 0 || max_nodes == 0 {
                     return r#"<svg viewBox="0 0 1 1" xmlns="http://www.w3.org/2000/svg"></svg>"#
                         .into();
                 }
-                let start_node_id = (around_node_id.len() != 0).then(|| {
+                let start_node_id = (!around_node_id.is_empty()).then(|| {
                     let name = &around_node_id[4..];
                     dot.graph
                         .iter_nodes_with_id()
                         .filter_map(|(id, _)| {
-                            (dot.graph.original_id(id) == Some(&name)).then_some(id)
+                            (dot.graph.original_id(id).is_some_and(|oid| oid == name)).then_some(id)
                         })
                         .next()
                         .unwrap()
                 });
+                let mut options = LayoutOptions::from_attributes(&dot.attributes);
+                for (name, value) in &self.attributes {
+                    options.set(name, value);
+                }
                 let (dot, extra_edges) =
                     if dot.graph.nodes_count() > max_nodes || dot.graph.edges_count() > max_edges {
                         let (subgraph, extra_edges) =
@@ -100,7 +183,7 @@ impl Graph {
                     } else {
                         ((*dot).clone(), None)
                     };
-                std::str::from_utf8(&graph::full_draw(dot, extra_edges.as_ref()))
+                std::str::from_utf8(&graph::full_draw(dot, extra_edges.as_ref(), &options))
                     .unwrap()
                     .into()
             }
@@ -113,15 +196,15 @@ impl Graph {
             dot.graph
                 .iter_nodes_ids()
                 .filter_map(|id| {
-                    let &oid = dot.graph.original_id(id).unwrap();
-                    let label = dot.labels.get(id).unwrap_or("");
+                    let oid = dot.graph.original_id(id).unwrap();
+                    let label = dot.label(id).unwrap_or("");
                     (oid.to_lowercase().contains(value) || label.to_lowercase().contains(value))
                         .then(|| SearchResultItem {
                             id: oid.to_string(),
                             label: label.to_string(),
                         })
                 })
-                .map(|id| JsValue::from(id))
+                .map(JsValue::from)
                 .collect::<js_sys::Array>()
         } else {
             js_sys::Array::new()
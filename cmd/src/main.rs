@@ -1,6 +1,6 @@
-use graph::{generator, read_dot};
+use graph::{generator, layout::LayoutOptions, read_dot, OutputFormat};
 extern crate simplelog;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::{fs::File, io::Read, path::PathBuf, str};
 
 fn main() {
@@ -26,10 +26,22 @@ fn main() {
         } => generator::random(nodes_count, edges_count),
     };
 
-    let dot = read_dot::parse(&data).expect("parse error");
+    let dot = match read_dot::parse(&data) {
+        Ok(dot) => dot,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{error}");
+            }
+            std::process::exit(1);
+        }
+    };
     if dot.graph.nodes_count() == 0 {
         return;
     }
+    let mut options = LayoutOptions::from_attributes(&dot.attributes);
+    for (name, value) in &args.graph_attributes {
+        options.set(name, value);
+    }
     let (dot, extra_edges) =
         if dot.graph.nodes_count() > args.max_nodes || dot.graph.edges_count() > args.max_edges {
             let (subdot, extra_edges) = graph::subgraph(&dot, None, args.max_nodes, args.max_edges);
@@ -37,7 +49,13 @@ fn main() {
         } else {
             (dot, None)
         };
-    let output = graph::full_draw(dot, extra_edges.as_ref());
+    let output = match args.format {
+        Format::Svg => graph::full_draw(dot, extra_edges.as_ref(), &options),
+        Format::Dot => graph::full_write(dot, OutputFormat::Dot, &options),
+        Format::Plain => graph::full_write(dot, OutputFormat::Plain, &options),
+        Format::PlainExt => graph::full_write(dot, OutputFormat::PlainExt, &options),
+        Format::Json => graph::full_write(dot, OutputFormat::Json, &options),
+    };
     let res = str::from_utf8(&output).expect("invalid utf");
     print!("{}", res);
 }
@@ -58,10 +76,39 @@ struct Cli {
     #[arg(short = 'l', long, default_value_t = simplelog::LevelFilter::Debug)]
     log_level: simplelog::LevelFilter,
 
+    /// output format
+    #[arg(short = 'T', long, value_enum, default_value_t = Format::Svg)]
+    format: Format,
+
+    /// layout graph attribute like nodesep=0.5, overrides the file ones
+    #[arg(short = 'G', value_name = "NAME=VALUE", value_parser = parse_attribute)]
+    graph_attributes: Vec<(String, String)>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+fn parse_attribute(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected NAME=VALUE, got {value}"))
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// SVG image
+    Svg,
+    /// DOT with layout attributes like `dot -Tdot`
+    Dot,
+    /// Graphviz `plain` text
+    Plain,
+    /// Graphviz `plain-ext` text, `plain` with edge ports
+    PlainExt,
+    /// Graphviz `json0`
+    Json,
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// read dot file
@@ -4,10 +4,11 @@ use std::fmt::Debug;
 pub fn add_virtual_nodes<T: Debug>(graph: &mut DirectedGraph<T>, ranks: &mut NodeMap<i32>) {
     graph.for_each_edge_mut(&mut |graph, mut edge_id| {
         let edge = graph.edge(edge_id);
-        if edge.from == edge.to {
+        if edge.from == edge.to || ranks.get(edge.from) == ranks.get(edge.to) {
             return;
         }
         let is_inverted = edge.kind == EdgeKind::Inverted;
+        let weight = edge.weight;
         let from_id = edge.from;
         let to_id = edge.to;
         let mut from_rank = *ranks.get(from_id);
@@ -36,6 +37,7 @@ pub fn add_virtual_nodes<T: Debug>(graph: &mut DirectedGraph<T>, ranks: &mut Nod
             } else {
                 Edge::new(node_id, to_id)
             });
+            graph.edge_mut(new_edge_id).weight = weight;
             graph.node_mut(node_id).outputs.push(new_edge_id);
             from_rank += 1;
 
@@ -0,0 +1,190 @@
+use crate::graph::*;
+use crate::label;
+use crate::read_dot::{Attributes, DotGraph, Subgraph};
+
+/// Clusters of the DOT file flattened to a vector, non cluster subgraphs are
+/// skipped and their clusters are attached to the closest cluster parent.
+#[derive(Clone, Debug, Default)]
+pub struct Clusters<'a> {
+    pub clusters: Vec<Cluster<'a>>,
+    /// The deepest cluster of every node, virtual nodes included.
+    node_clusters: NodeMap<Option<usize>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Cluster<'a> {
+    pub parent: Option<usize>,
+    pub attributes: Attributes<'a>,
+}
+
+impl Cluster<'_> {
+    /// Height of the label text, 0 for clusters without labels.
+    pub fn label_height(&self) -> f64 {
+        label::text_size(&self.attributes, "label").map_or(0., |(_, height)| height)
+    }
+}
+
+impl<'a> Clusters<'a> {
+    pub fn new(dot: &DotGraph<'a>) -> Self {
+        let mut clusters = Self {
+            clusters: vec![],
+            node_clusters: dot.graph.node_map(),
+        };
+        for subgraph in &dot.subgraphs {
+            clusters.add_subgraph(subgraph, None);
+        }
+        clusters
+    }
+
+    fn add_subgraph(&mut self, subgraph: &Subgraph<'a>, parent: Option<usize>) {
+        let parent = if subgraph.is_cluster() {
+            let index = self.clusters.len();
+            self.clusters.push(Cluster {
+                parent,
+                attributes: subgraph.attributes.clone(),
+            });
+            // Children are visited later and take their nodes, a node in two
+            // unrelated clusters stays in the first one.
+            for &id in &subgraph.nodes {
+                let current = *self.node_clusters.get(id);
+                if current.is_none_or(|current| self.is_ancestor(current, index)) {
+                    self.node_clusters.set(id, Some(index));
+                }
+            }
+            Some(index)
+        } else {
+            parent
+        };
+        for child in &subgraph.subgraphs {
+            self.add_subgraph(child, parent);
+        }
+    }
+
+    /// Assigns virtual nodes of the split edges to the deepest cluster
+    /// containing both ends of the edge, must be called after
+    /// [`crate::add_virtual_nodes::add_virtual_nodes`].
+    pub fn add_virtual_nodes<T>(&mut self, graph: &DirectedGraph<T>, original_edges_count: u32) {
+        if self.is_empty() {
+            return;
+        }
+        for n in 0..original_edges_count {
+            let edge = graph.edge(EdgeId::from(n));
+            let mut chain = vec![];
+            let mut to = edge.to;
+            while graph.node(to).is_virtual {
+                chain.push(to);
+                to = graph.edge(graph.node(to).outputs[0]).to;
+            }
+            let common = self.common_ancestor(self.cluster(edge.from), self.cluster(to));
+            for id in chain {
+                self.node_clusters.set(id, common);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clusters.is_empty()
+    }
+
+    pub fn cluster(&self, id: NodeId) -> Option<usize> {
+        if self.is_empty() {
+            None
+        } else {
+            *self.node_clusters.get(id)
+        }
+    }
+
+    /// Clusters of the node from the top level to the deepest one.
+    pub fn path(&self, id: NodeId) -> Vec<usize> {
+        let mut path = vec![];
+        let mut current = self.cluster(id);
+        while let Some(cluster) = current {
+            path.push(cluster);
+            current = self.clusters[cluster].parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// Number of nesting levels inside the cluster, 0 for a cluster without
+    /// nested clusters.
+    pub fn levels_below(&self, cluster: usize) -> u32 {
+        self.clusters
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.parent == Some(cluster))
+            .map(|(n, _)| self.levels_below(n) + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn is_ancestor(&self, ancestor: usize, mut cluster: usize) -> bool {
+        while let Some(parent) = self.clusters[cluster].parent {
+            if parent == ancestor {
+                return true;
+            }
+            cluster = parent;
+        }
+        false
+    }
+
+    fn common_ancestor(&self, a: Option<usize>, b: Option<usize>) -> Option<usize> {
+        let mut current = a;
+        while let Some(cluster) = current {
+            if Some(cluster) == b || b.is_some_and(|b| self.is_ancestor(cluster, b)) {
+                return Some(cluster);
+            }
+            current = self.clusters[cluster].parent;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{add_virtual_nodes::add_virtual_nodes, read_dot::parse};
+
+    #[test]
+    fn nested() {
+        let dot = parse(
+            "digraph {
+                subgraph cluster_a { a; subgraph { subgraph cluster_b { b } } }
+                subgraph cluster_c { c; a }
+                d
+            }",
+        )
+        .unwrap();
+        let clusters = Clusters::new(&dot);
+        assert_eq!(clusters.clusters.len(), 3);
+        assert_eq!(clusters.clusters[1].parent, Some(0));
+        assert_eq!(clusters.cluster(NodeId::from(0u32)), Some(0));
+        assert_eq!(clusters.path(NodeId::from(1u32)), &[0, 1]);
+        assert_eq!(clusters.cluster(NodeId::from(2u32)), Some(2));
+        assert_eq!(clusters.cluster(NodeId::from(3u32)), None);
+        assert_eq!(clusters.levels_below(0), 1);
+        assert_eq!(clusters.levels_below(2), 0);
+    }
+
+    #[test]
+    fn virtual_nodes() {
+        let mut dot = parse(
+            "digraph {
+                subgraph cluster_a { a -> b; subgraph cluster_b { c } }
+                a -> c; a -> d;
+            }",
+        )
+        .unwrap();
+        let mut ranks = dot.graph.node_map();
+        ranks.set(NodeId::from(1u32), 2);
+        ranks.set(NodeId::from(2u32), 2);
+        ranks.set(NodeId::from(3u32), 2);
+        let edges_count = dot.graph.edges_count();
+        let mut clusters = Clusters::new(&dot);
+        add_virtual_nodes(&mut dot.graph, &mut ranks);
+        clusters.add_virtual_nodes(&dot.graph, edges_count);
+        assert_eq!(clusters.cluster(NodeId::from(4u32)), Some(0));
+        assert_eq!(clusters.cluster(NodeId::from(5u32)), Some(0));
+        assert_eq!(clusters.cluster(NodeId::from(6u32)), None);
+    }
+}
@@ -1,203 +1,526 @@
+use crate::cluster::Clusters;
 use crate::graph::*;
+use crate::label;
+use crate::layout::{BackEdges, Layout, LayoutOptions, RankDir};
+use crate::multi_edge;
+use crate::read_dot::Attributes;
 use crate::read_dot::DotGraph;
+use crate::record;
+use crate::shape::Shape;
+use crate::text;
 
+use std::borrow::Cow;
 use std::io::Write;
-use svg::node::element::{Ellipse, Marker, Path, Text, SVG};
+use svg::node::element::{Ellipse, Line, Marker, Path, Polygon, Rectangle, Text, SVG};
 use svg::node::Text as NodeText;
 use svg::Document;
 
+/// Color of back edges with [`BackEdges::Highlight`].
+const BACK_EDGE_COLOR: &str = "red";
+
 pub fn draw<'a, W: Write>(
     dot: &DotGraph<'a>,
-    ranks: &NodeMap<i32>,
-    places: &NodeMap<u32>,
+    layout: &Layout,
     extra_edges: Option<&NodeMap<(u32, u32)>>,
+    options: &LayoutOptions,
     write: W,
 ) {
     let graph = &dot.graph;
-    let x_step = 70. / 50.;
-    let y_step = 70.;
-    let rx = 20f64;
-    let ry = 10f64;
-    let mut max_x = 0;
-    let mut max_y = 0;
+    let highlight = options.back_edges == BackEdges::Highlight;
 
     let mut document = Document::new();
-    document = document.add(
-        Marker::new()
-            .set("id", "arrow")
-            .set("viewBox", "0, 0, 7, 4")
-            .set("refX", 5)
-            .set("refY", 2)
-            .set("markerUnits", "strokeWidth")
-            .set("markerWidth", "7")
-            .set("markerHeight", "4")
-            .set("orient", "auto")
-            .add(Path::new().set("d", "M 0 0 L 7 2 L 0 4 z")),
-    );
-    document = document.add(
-        Marker::new()
-            .set("id", "arrow-inverted")
-            .set("viewBox", "0, 0, 7, 4")
-            .set("refX", 2)
-            .set("refY", 2)
-            .set("markerUnits", "strokeWidth")
-            .set("markerWidth", "7")
-            .set("markerHeight", "4")
-            .set("orient", "auto")
-            .add(Path::new().set("d", "M 7 0 L 0 2 L 7 4 z")),
-    );
-
-    for (id, node) in graph.iter_nodes_with_id() {
-        for edge in node.outputs.iter().map(|&id| graph.edge(id)) {
-            let to_rank = *ranks.get(edge.to);
-            let from_rank = *ranks.get(edge.from);
-            let (from_rank, to_rank, to_id) = if from_rank < to_rank {
-                (from_rank, to_rank, edge.to)
-            } else {
-                (to_rank, from_rank, edge.from)
-            };
-
-            let (marker_start, marker_end) = if edge.is_inverted() && !node.is_virtual {
-                ("url(#arrow-inverted)", "")
-            } else if !edge.is_inverted() && !graph.node(to_id).is_virtual {
-                ("", "url(#arrow)")
-            } else {
-                ("", "")
-            };
-
-            let y_start = if node.is_virtual {
-                from_rank as f64 * y_step
-            } else {
-                from_rank as f64 * y_step + ry + if marker_start.is_empty() { -0.2 } else { 1. }
-            };
+    document = document.add(arrow_marker("arrow", "black"));
+    if highlight {
+        document = document.add(arrow_marker("back-arrow", BACK_EDGE_COLOR));
+    }
+    // Clusters in order of appearance, so nested ones are drawn on top of
+    // their parents.
+    let clusters = Clusters::new(dot);
+    for (cluster, b) in clusters.clusters.iter().zip(&layout.clusters) {
+        let Some(b) = b else {
+            continue;
+        };
+        let attributes = &cluster.attributes;
+        let fill = attributes
+            .get("bgcolor")
+            .or_else(|| {
+                attributes
+                    .get("style")
+                    .filter(|s| s.contains("filled"))
+                    .and_then(|_| attributes.get("fillcolor").or(attributes.get("color")))
+            })
+            .map_or("none", |v| v.as_ref());
+        document = document.add(
+            Rectangle::new()
+                .set("x", b.x)
+                .set("y", b.y)
+                .set("width", b.width)
+                .set("height", b.height)
+                .set("fill", fill)
+                .set(
+                    "stroke",
+                    attributes.get("color").map_or("black", |v| v.as_ref()),
+                )
+                .set("stroke-width", 0.5),
+        );
+        if let Some(label) = attributes.get("label") {
+            document = document.add(
+                Text::new()
+                    .add(NodeText::new(label.as_ref()))
+                    .set("x", b.center().0)
+                    .set("y", b.y + 1.)
+                    .set("dominant-baseline", "hanging")
+                    .set("text-anchor", "middle")
+                    .set("font-family", font_family(attributes))
+                    .set("font-size", text::font(attributes).1),
+            );
+        }
+    }
 
-            let y_end = if graph.node(to_id).is_virtual {
-                to_rank as f64 * y_step
+    let edges = layout
+        .edges
+        .iter()
+        .map(|(edge_id, edge)| (edge, dot.edge_attributes.get(edge_id)));
+    for (edge, attributes) in edges.chain(layout.self_edges.iter().zip(&dot.self_edge_attributes)) {
+        // Merged into another edge.
+        if edge.count == 0 {
+            continue;
+        }
+        let mut path = format!("M{},{}", edge.points[0].0, edge.points[0].1);
+        for curve in edge.points[1..].chunks(3) {
+            let points: Vec<_> = curve.iter().map(|(x, y)| format!("{x},{y}")).collect();
+            path += &format!(" C{}", points.join(" "));
+        }
+        let is_highlighted = highlight && edge.back;
+        let marker_end = match (edge.arrow, is_highlighted) {
+            (None, _) => "",
+            (Some(_), false) => "url(#arrow)",
+            (Some(_), true) => "url(#back-arrow)",
+        };
+        let stroke = if is_highlighted {
+            BACK_EDGE_COLOR
+        } else {
+            "black"
+        };
+        let mut path = Path::new()
+            .set("stroke", stroke)
+            .set("marker-end", marker_end)
+            .set("fill", "none")
+            .set("d", path);
+        if is_highlighted {
+            path = path.set("stroke-dasharray", "5,2");
+        }
+        document = document.add(path);
+        for (name, center) in [
+            ("label", edge.label),
+            ("headlabel", edge.head_label),
+            ("taillabel", edge.tail_label),
+            ("xlabel", edge.xlabel),
+        ] {
+            let text = if name == "label" {
+                multi_edge::edge_label(attributes, edge.count)
             } else {
-                to_rank as f64 * y_step - ry - if marker_end.is_empty() { -0.2 } else { 1. }
+                attributes.get(name).map(|t| Cow::Borrowed(t.as_ref()))
             };
-
-            let t = Path::new()
-                .set("stroke", "black")
-                .set("marker-start", marker_start)
-                .set("marker-end", marker_end)
-                .set("fill", "none")
-                .set(
-                    "d",
-                    format!(
-                        "M{0},{1} C{0},{2},{3},{4} {3},{5}",
-                        rx + *places.get(id) as f64 * x_step,
-                        ry + y_start,
-                        ry + y_start + ry + ry,
-                        rx + *places.get(edge.to) as f64 * x_step,
-                        y_end - ry,
-                        ry + y_end,
-                    ),
-                );
-            document = document.add(t);
+            if let (Some(text), Some(center)) = (text, center) {
+                let font = label::font_attributes(attributes, name);
+                for line in label(&text, center, &font, None) {
+                    document = document.add(line);
+                }
+            }
         }
+    }
 
+    for (id, node) in graph.iter_nodes_with_id() {
         if !node.is_virtual {
+            let rect = layout.nodes.get(id);
             let mut group = SVG::new()
-                .set("x", *places.get(id) as f64 * x_step)
-                .set("y", *ranks.get(id) as f64 * y_step)
-                .set("width", rx * 2.)
-                .set("height", ry * 2.);
+                .set("x", rect.x)
+                .set("y", rect.y)
+                .set("width", rect.width)
+                .set("height", rect.height);
 
             let svg_id = format!("svg_{}", graph.original_id(id).unwrap());
+            let name = dot
+                .label(id)
+                .or_else(|| graph.original_id(id).map(|v| v.as_ref()));
+            let attributes = dot.node_attributes.get(id);
+            let horizontal = !layout.rankdir.is_horizontal();
+            let fields = record::node_fields(attributes, name.unwrap_or_default(), horizontal)
+                .map(|(fields, _)| fields);
 
-            group = group.add(
-                Ellipse::new()
-                    .set("cx", "50%")
-                    .set("cy", "50%")
-                    .set("rx", "48%")
-                    .set("ry", "47%")
-                    .set("fill", "silver")
-                    .set("stroke", "black")
-                    .set("stroke-width", 1)
-                    .set("onClick", format!("outputClickHandler('{}')", &svg_id))
-                    .set("id", svg_id.as_str()),
-            );
-
-            if let Some(name) = dot
-                .labels
-                .get(id)
-                .or_else(|| graph.original_id(id).map(|s| *s))
-            {
+            if let Some(fields) = &fields {
+                let rounded = attributes.get("shape").is_some_and(|s| s == "Mrecord");
                 group = group.add(
-                    Text::new()
-                        .add(NodeText::new(name))
-                        .set("x", "50%")
-                        .set("y", "50%")
+                    Rectangle::new()
+                        .set("x", 0.5)
+                        .set("y", 0.5)
+                        .set("width", rect.width - 1.)
+                        .set("height", rect.height - 1.)
+                        .set("rx", if rounded { 4 } else { 0 })
+                        .set("fill", "silver")
+                        .set("stroke", "black")
+                        .set("stroke-width", 1)
                         .set("onClick", format!("outputClickHandler('{}')", &svg_id))
-                        .set("dominant-baseline", "middle")
-                        .set("text-anchor", "middle")
-                        .set("font-size", 4),
+                        .set("id", svg_id.as_str()),
                 );
+                for field in fields {
+                    let (x, y) = (field.x * rect.width, field.y * rect.height);
+                    let (width, height) = (field.width * rect.width, field.height * rect.height);
+                    // Separators on the left and top sides, the outer ones
+                    // are covered by the node border.
+                    if field.x > 0. {
+                        group = group.add(separator(x, y, x, y + height));
+                    }
+                    if field.y > 0. {
+                        group = group.add(separator(x, y, x + width, y));
+                    }
+                    let center = (x + width / 2., y + height / 2.);
+                    for line in label(&field.text, center, attributes, Some(&svg_id)) {
+                        group = group.add(line);
+                    }
+                }
+            } else {
+                let shape = Shape::from_attributes(attributes);
+                group = draw_shape(group, shape, (rect.width, rect.height), &svg_id);
+            }
+
+            let shape = Shape::from_attributes(attributes);
+            if let Some(name) = name.filter(|_| fields.is_none() && shape.has_label()) {
+                let center = (rect.width / 2., rect.height / 2.);
+                for line in label(name, center, attributes, Some(&svg_id)) {
+                    group = group.add(line);
+                }
             }
 
+            // Counters are on the sides where the hidden edges come in and
+            // go out.
             let (extra_in_count, extra_out_count) =
                 extra_edges.map(|v| *v.get(id)).unwrap_or((0, 0));
-            if extra_in_count != 0 {
-                group = group.add(
-                    Text::new()
-                        .add(NodeText::new(format!("←{extra_in_count}")))
-                        .set("x", "100%")
-                        .set("y", "12%")
-                        .set("text-anchor", "end")
-                        .set("font-size", 3),
-                );
-            }
-            if extra_out_count != 0 {
-                group = group.add(
-                    Text::new()
-                        .add(NodeText::new(format!("→{extra_out_count}")))
-                        .set("x", "100%")
-                        .set("y", "98%")
-                        .set("text-anchor", "end")
-                        .set("font-size", 3),
-                );
+            let (in_position, out_position) = counter_positions(layout.rankdir);
+            let counter_size = text::font(attributes).1 * 0.75;
+            for (count, text, (x, y, anchor)) in [
+                (extra_in_count, "←", in_position),
+                (extra_out_count, "→", out_position),
+            ] {
+                if count != 0 {
+                    group = group.add(
+                        Text::new()
+                            .add(NodeText::new(format!("{text}{count}")))
+                            .set("x", x)
+                            .set("y", y)
+                            .set("text-anchor", anchor)
+                            .set("font-size", counter_size),
+                    );
+                }
             }
 
             document = document.add(group);
         }
-        max_x = max_x.max(*places.get(id));
-        max_y = max_y.max(*ranks.get(id));
     }
 
-    document = document.set(
-        "viewBox",
-        (
-            0.,
-            0,
-            max_x as f64 * x_step as f64 + rx * 2.,
-            max_y as f64 * y_step + rx,
-        ),
-    );
+    let b = layout.bounding_box;
+    document = document.set("viewBox", (b.x, b.y, b.width, b.height));
 
     let _todo = svg::write(write, &document);
 }
 
+/// Adds the outline of a node of the given size, the element with `svg_id`
+/// handles clicks.
+fn draw_shape(group: SVG, shape: Shape, (width, height): (f64, f64), svg_id: &str) -> SVG {
+    let on_click = format!("outputClickHandler('{svg_id}')");
+    let (rx, ry) = (width / 2. - 0.5, height / 2. - 0.5);
+    let ellipse = |rx: f64, ry: f64| {
+        Ellipse::new()
+            .set("cx", width / 2.)
+            .set("cy", height / 2.)
+            .set("rx", rx)
+            .set("ry", ry)
+            .set("fill", "silver")
+            .set("stroke", "black")
+            .set("stroke-width", 1)
+    };
+    let path = |d: String| {
+        Path::new()
+            .set("d", d)
+            .set("fill", "silver")
+            .set("stroke", "black")
+            .set("stroke-width", 1)
+    };
+    // Sides of the outline are in the middle of the border line.
+    let (w, h) = (width - 1., height - 1.);
+    match shape {
+        Shape::Ellipse | Shape::Circle => group.add(
+            Ellipse::new()
+                .set("cx", "50%")
+                .set("cy", "50%")
+                .set("rx", "48%")
+                .set("ry", "47%")
+                .set("fill", "silver")
+                .set("stroke", "black")
+                .set("stroke-width", 1)
+                .set("onClick", on_click)
+                .set("id", svg_id),
+        ),
+        Shape::DoubleCircle => group
+            .add(ellipse(rx, ry).set("onClick", on_click).set("id", svg_id))
+            .add(ellipse(rx - 4., ry - 4.)),
+        Shape::Point => group.add(
+            ellipse(rx, ry)
+                .set("fill", "black")
+                .set("onClick", on_click)
+                .set("id", svg_id),
+        ),
+        Shape::PlainText { .. } => group.add(
+            Rectangle::new()
+                .set("width", width)
+                .set("height", height)
+                .set("fill", "transparent")
+                .set("onClick", on_click)
+                .set("id", svg_id),
+        ),
+        Shape::Note => {
+            let fold = f64::min(w, h) / 4.;
+            group
+                .add(
+                    path(format!(
+                        "M0.5,0.5 H{} L{},{} V{} H0.5 Z",
+                        0.5 + w - fold,
+                        0.5 + w,
+                        0.5 + fold,
+                        0.5 + h
+                    ))
+                    .set("onClick", on_click)
+                    .set("id", svg_id),
+                )
+                .add(path(format!(
+                    "M{},0.5 V{} H{}",
+                    0.5 + w - fold,
+                    0.5 + fold,
+                    0.5 + w
+                )))
+        }
+        Shape::Folder => {
+            let tab = f64::min(w, h) / 5.;
+            let points = [
+                (0., tab),
+                (w * 0.6, tab),
+                (w * 0.6 + tab, 0.),
+                (w - tab, 0.),
+                (w, tab),
+                (w, h),
+                (0., h),
+            ];
+            group.add(polygon(&points).set("onClick", on_click).set("id", svg_id))
+        }
+        Shape::Cylinder => {
+            let (rx, ry) = (w / 2., h / 10.);
+            group
+                .add(
+                    path(format!(
+                        "M0.5,{top} V{bottom} A{rx},{ry} 0 0 0 {right},{bottom} V{top} \
+                         A{rx},{ry} 0 0 0 0.5,{top} Z",
+                        top = 0.5 + ry,
+                        bottom = 0.5 + h - ry,
+                        right = 0.5 + w,
+                    ))
+                    .set("onClick", on_click)
+                    .set("id", svg_id),
+                )
+                .add(
+                    path(format!(
+                        "M0.5,{top} A{rx},{ry} 0 0 0 {right},{top}",
+                        top = 0.5 + ry,
+                        right = 0.5 + w,
+                    ))
+                    .set("fill", "none"),
+                )
+        }
+        Shape::Box | Shape::Polygon { .. } => {
+            let points = shape.polygon((w, h)).unwrap_or_default();
+            group.add(polygon(&points).set("onClick", on_click).set("id", svg_id))
+        }
+    }
+}
+
+/// Polygon with vertices shifted by the half of the border line.
+fn polygon(points: &[(f64, f64)]) -> Polygon {
+    let points: Vec<_> = points
+        .iter()
+        .map(|(x, y)| format!("{},{}", x + 0.5, y + 0.5))
+        .collect();
+    Polygon::new()
+        .set("points", points.join(" "))
+        .set("fill", "silver")
+        .set("stroke", "black")
+        .set("stroke-width", 1)
+}
+
+/// Arrow head marker with the given id and color.
+fn arrow_marker(id: &str, fill: &str) -> Marker {
+    Marker::new()
+        .set("id", id)
+        .set("viewBox", "0, 0, 7, 4")
+        .set("refX", 5)
+        .set("refY", 2)
+        .set("markerUnits", "strokeWidth")
+        .set("markerWidth", "7")
+        .set("markerHeight", "4")
+        .set("orient", "auto")
+        .add(
+            Path::new()
+                .set("d", "M 0 0 L 7 2 L 0 4 z")
+                .set("fill", fill),
+        )
+}
+
+/// Lines of a node label centered at `center`.
+fn label(
+    text: &str,
+    center: (f64, f64),
+    attributes: &Attributes,
+    svg_id: Option<&str>,
+) -> Vec<Text> {
+    let (_, size) = text::font(attributes);
+    let lines = text::lines(text);
+    let first = center.1 - (lines.len() - 1) as f64 * size * text::LINE_HEIGHT / 2.;
+    lines
+        .iter()
+        .enumerate()
+        .map(|(n, line)| {
+            let text = Text::new()
+                .add(NodeText::new(*line))
+                .set("x", center.0)
+                .set("y", first + n as f64 * size * text::LINE_HEIGHT);
+            match svg_id {
+                Some(svg_id) => text.set("onClick", format!("outputClickHandler('{svg_id}')")),
+                None => text,
+            }
+            .set("dominant-baseline", "middle")
+            .set("text-anchor", "middle")
+            .set("font-family", font_family(attributes))
+            .set("font-size", size)
+        })
+        .collect()
+}
+
+/// CSS font family with the generic family of the measured font as fallback.
+fn font_family(attributes: &Attributes) -> String {
+    let generic = match text::font(attributes).0 {
+        text::Font::Times => "serif",
+        text::Font::Helvetica => "sans-serif",
+        text::Font::Courier => "monospace",
+    };
+    match attributes.get("fontname") {
+        Some(name) => format!("{name},{generic}"),
+        None => format!("Times,{generic}"),
+    }
+}
+
+type CounterPosition = (&'static str, &'static str, &'static str);
+
+/// `x`, `y` and `text-anchor` of the input and output edge counters.
+fn counter_positions(rankdir: RankDir) -> (CounterPosition, CounterPosition) {
+    let (top, bottom) = (("100%", "12%", "end"), ("100%", "98%", "end"));
+    let (left, right) = (("2%", "98%", "start"), ("100%", "98%", "end"));
+    match rankdir {
+        RankDir::TopToBottom => (top, bottom),
+        RankDir::BottomToTop => (bottom, top),
+        RankDir::LeftToRight => (left, right),
+        RankDir::RightToLeft => (right, left),
+    }
+}
+
+fn separator(x1: f64, y1: f64, x2: f64, y2: f64) -> Line {
+    Line::new()
+        .set("x1", x1)
+        .set("y1", y1)
+        .set("x2", x2)
+        .set("y2", y2)
+        .set("stroke", "black")
+        .set("stroke-width", 0.5)
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::layout::LayoutOptions;
     use crate::read_dot::parse;
 
     use super::*;
 
     #[test]
     fn simple() {
-        let input = "digraph test { 0->2; 1->2; 0->3; 3->4; }";
-        let mut dot = parse(input).unwrap();
-        let mut ranks = dot.graph.node_map();
-        ranks.set(NodeId::from(2u32), 1);
-        ranks.set(NodeId::from(3u32), 1);
-        dot.graph.node_mut(NodeId::from(3u32)).is_virtual = true;
-        ranks.set(NodeId::from(4u32), 2);
-        let mut p = dot.graph.node_map();
-        p.set(NodeId::from(1u32), 1);
-        p.set(NodeId::from(3u32), 1);
+        let input = "digraph test { 0->2; 1->2; 0->3; 3->4; 0->4; }";
+        let dot = parse(input).unwrap();
+        let layout = crate::layout(&dot, &LayoutOptions::default());
+        let mut s = vec![];
+        draw(&dot, &layout, None, &LayoutOptions::default(), &mut s);
+        let svg = String::from_utf8(s).unwrap();
+        assert_eq!(svg.matches("<ellipse").count(), 5);
+        assert_eq!(svg.matches("<path").count(), 6);
+        // The long edge is a single path through its virtual node.
+        assert_eq!(svg.matches(" C").count(), 6);
+        assert_eq!(svg.matches("font-size=\"14\"").count(), 5);
+
+        let dot = parse("digraph { a [label=\"x\\ny\", fontsize=10] }").unwrap();
+        let layout = crate::layout(&dot, &LayoutOptions::default());
+        let mut s = vec![];
+        draw(&dot, &layout, None, &LayoutOptions::default(), &mut s);
+        let svg = String::from_utf8(s).unwrap();
+        assert_eq!(svg.matches("font-size=\"10\"").count(), 2);
+
+        // Two lines 12 points apart around the middle of the 46 points high
+        // node.
+        assert!(svg.contains("y=\"17\">\nx\n</text>"));
+        assert!(svg.contains("y=\"29\">\ny\n</text>"));
+    }
+
+    #[test]
+    fn edge_labels() {
+        let input = "digraph { a -> b [label=x, headlabel=h, labelfontsize=8, xlabel=y] }";
+        let dot = parse(input).unwrap();
+        let layout = crate::layout(&dot, &LayoutOptions::default());
+        let mut s = vec![];
+        draw(&dot, &layout, None, &LayoutOptions::default(), &mut s);
+        let svg = String::from_utf8(s).unwrap();
+        assert_eq!(svg.matches("<text").count(), 5);
+        assert_eq!(svg.matches("font-size=\"8\"").count(), 1);
+        // Only node labels handle clicks.
+        assert_eq!(svg.matches("onClick").count(), 4);
+    }
+
+    #[test]
+    fn back_edges() {
+        let dot = parse("digraph { a -> b -> c -> d; d -> a; a -> c }").unwrap();
+        let layout = crate::layout(&dot, &LayoutOptions::default());
+        let mut s = vec![];
+        draw(&dot, &layout, None, &LayoutOptions::default(), &mut s);
+        let svg = String::from_utf8(s).unwrap();
+        assert!(!svg.contains("red"));
+
+        let mut options = LayoutOptions::default();
+        options.set("backedges", "highlight");
+        let mut s = vec![];
+        draw(&dot, &layout, None, &options, &mut s);
+        let svg = String::from_utf8(s).unwrap();
+        assert_eq!(svg.matches("stroke-dasharray").count(), 1);
+        assert_eq!(svg.matches("url(#back-arrow)").count(), 1);
+    }
+
+    #[test]
+    fn shapes() {
+        let input = "digraph { a [shape=box]; b [shape=point]; c [shape=doublecircle]; \
+                     d [shape=polygon, sides=5]; e [shape=plaintext]; f [shape=cylinder] }";
+        let dot = parse(input).unwrap();
+        let layout = crate::layout(&dot, &LayoutOptions::default());
         let mut s = vec![];
-        draw(&dot, &ranks, &p, None, &mut s);
-        // assert_eq!(std::str::from_utf8(&s[..]).unwrap(), ""); TODO
+        draw(&dot, &layout, None, &LayoutOptions::default(), &mut s);
+        let svg = String::from_utf8(s).unwrap();
+        assert_eq!(svg.matches("<polygon").count(), 2);
+        assert_eq!(svg.matches("<ellipse").count(), 3);
+        // The arrow marker and the cylinder body and rim.
+        assert_eq!(svg.matches("<path").count(), 3);
+        // Point nodes have no label.
+        assert_eq!(svg.matches("<text").count(), 5);
     }
 }
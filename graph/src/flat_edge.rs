@@ -0,0 +1,64 @@
+//! Flat edges, the ones with both ends on one rank because of `rank=same` or
+//! `minlen=0`. [`crate::place`] puts heads to the right of their tails. Edges
+//! between neighbor nodes without labels are straight, the others go as arcs
+//! above the rank with the label on top, the space for them is reserved
+//! between the ranks.
+
+use crate::label::LABEL_GAP;
+use crate::layout::Point;
+
+/// Height of arcs above the rank top.
+pub const ARC_HEIGHT: f64 = 16.;
+
+/// Space above the rank taken by an arc with a label of the given height.
+pub fn space_above(label_height: Option<f64>) -> f64 {
+    ARC_HEIGHT + label_height.map_or(0., |height| LABEL_GAP + height)
+}
+
+/// Control points of a curve from `start` to `end` with the highest point
+/// at `top`, it leaves and enters nodes vertically.
+pub fn arc(start: Point, end: Point, top: f64) -> Vec<Point> {
+    // The middle of a cubic curve is 3/4 of the way from the ends to the
+    // control points.
+    let control = |y: f64| y + (top - y) * 4. / 3.;
+    vec![
+        start,
+        (start.0, control(start.1)),
+        (end.0, control(end.1)),
+        end,
+    ]
+}
+
+/// Polyline from `start` up to `top`, across and down to `end`.
+pub fn arc_polyline(start: Point, end: Point, top: f64) -> [Point; 4] {
+    [start, (start.0, top), (end.0, top), end]
+}
+
+/// Center of a label of the given size above the arc.
+pub fn label_center(start: Point, end: Point, top: f64, (_, height): (f64, f64)) -> Point {
+    ((start.0 + end.0) / 2., top - LABEL_GAP - height / 2.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spline;
+
+    #[test]
+    fn arcs() {
+        let points = arc((0., 20.), (60., 20.), 0.);
+        let curve = [points[0], points[1], points[2], points[3]];
+        let middle = spline::bezier(&curve, 0.5);
+        assert!((middle.0 - 30.).abs() < 1e-9 && middle.1.abs() < 1e-9);
+        // The curve stays between the ends and below the top.
+        for n in 0..=10 {
+            let (x, y) = spline::bezier(&curve, n as f64 / 10.);
+            assert!((0. ..=60.).contains(&x) && (-1e-9..=20.).contains(&y));
+        }
+        assert_eq!(
+            label_center((0., 20.), (60., 20.), 0., (10., 8.)),
+            (30., -8.)
+        );
+        assert_eq!(space_above(None), ARC_HEIGHT);
+    }
+}
@@ -115,7 +115,7 @@ impl<T> DirectedGraph<T> {
     }
 
     pub fn iter_nodes_ids(&self) -> impl Iterator<Item = NodeId> {
-        (0..self.nodes_count()).map(|n| NodeId::from(n))
+        (0..self.nodes_count()).map(NodeId::from)
     }
 
     pub fn iter_nodes_with_id(&self) -> impl Iterator<Item = (NodeId, &Node)> {
@@ -178,7 +178,7 @@ impl<T> DirectedGraph<T> {
         };
         (start..self.edges_count())
             .chain(0..start)
-            .map(move |i| EdgeId::from(i))
+            .map(EdgeId::from)
     }
 
     pub fn iter_edges(&self) -> impl Iterator<Item = &Edge> {
@@ -198,20 +198,46 @@ impl<T> DirectedGraph<T> {
         }
     }
 
+    /// Adds a loop of the node, loops are not in [`DirectedGraph::iter_edges`].
+    pub fn add_self_edge(&mut self, node: NodeId) {
+        self.self_edges.push(node);
+    }
+
     // TOOD: refactor to add edge to nodes
     pub fn add_edge(&mut self, edge: Edge) -> EdgeId {
         self.edges.push(edge);
         EdgeId::from(self.edges.len() as u32 - 1)
     }
 
+    /// Removes edges for which `keep` is false, kept edges get new ids in the
+    /// same order. Returns new ids by old ones.
+    pub fn retain_edges<F>(&mut self, keep: F) -> EdgeMap<Option<EdgeId>>
+    where
+        F: Fn(EdgeId, &Edge) -> bool,
+    {
+        let mut ids = self.edge_map();
+        let mut edges = Vec::with_capacity(self.edges.len());
+        for (n, edge) in std::mem::take(&mut self.edges).into_iter().enumerate() {
+            let id = EdgeId::from(n as u32);
+            if keep(id, &edge) {
+                ids.set(id, Some(EdgeId::from(edges.len() as u32)));
+                edges.push(edge);
+            }
+        }
+        self.edges = edges;
+        for node in &mut self.nodes {
+            node.inputs = node.inputs.iter().filter_map(|&e| *ids.get(e)).collect();
+            node.outputs = node.outputs.iter().filter_map(|&e| *ids.get(e)).collect();
+        }
+        ids
+    }
+
     pub fn node_map<V: Default + Clone>(&self) -> NodeMap<V> {
         NodeMap::new(self.nodes_count())
     }
 
     pub fn edge_map<V: Default + Clone>(&self) -> EdgeMap<V> {
-        let mut values = vec![];
-        values.resize(self.edges_count() as usize, V::default());
-        EdgeMap { values }
+        EdgeMap::new(self.edges_count())
     }
 
     #[cfg(target_arch = "wasm32")]
@@ -248,7 +274,7 @@ impl<T> DirectedGraph<T> {
             return;
         };
 
-        let mut file = File::create(&format!("{filename_prefix}_{ending}.dot")).unwrap();
+        let mut file = File::create(format!("{filename_prefix}_{ending}.dot")).unwrap();
         let mut buf = "digraph temp {".to_string();
 
         for (id, node) in self
@@ -365,8 +391,7 @@ impl<T: Default + Clone> NodeMap<T> {
         self.values
             .iter()
             .enumerate()
-            .filter(|(_, v)| predicate(v))
-            .next()
+            .find(|(_, v)| predicate(v))
             .map(|(n, _)| NodeId(n as u32))
     }
 
@@ -390,6 +415,12 @@ impl<T: Default + Clone> NodeMap<T> {
 }
 
 impl<T: Default + Clone> EdgeMap<T> {
+    pub fn new(size: u32) -> Self {
+        Self {
+            values: vec![T::default(); size as usize],
+        }
+    }
+
     pub fn get(&self, id: EdgeId) -> &T {
         assert!((id.0 as usize) < self.values.len());
         &self.values[id.0 as usize]
@@ -414,8 +445,7 @@ impl<T: Default + Clone> EdgeMap<T> {
         self.values
             .iter()
             .enumerate()
-            .filter(|(_, v)| predicate(v))
-            .next()
+            .find(|(_, v)| predicate(v))
             .map(|(n, _)| EdgeId(n as u32))
     }
 
@@ -458,7 +488,7 @@ impl Node {
     }
 
     pub fn edges(&self) -> impl Iterator<Item = EdgeId> + '_ {
-        self.inputs.iter().chain(self.outputs.iter()).map(|v| *v)
+        self.inputs.iter().chain(self.outputs.iter()).copied()
     }
 }
 
@@ -470,6 +500,7 @@ impl Edge {
             kind: EdgeKind::Normal,
             min_length: 1,
             weight: 1,
+            constraint: true,
         }
     }
 
@@ -480,12 +511,16 @@ impl Edge {
             kind: EdgeKind::Inverted,
             min_length: 1, //TODO copy
             weight: 1,
+            constraint: true,
         }
     }
 
     pub fn invert(&mut self) {
         std::mem::swap(&mut self.from, &mut self.to);
-        self.kind = EdgeKind::Inverted;
+        self.kind = match self.kind {
+            EdgeKind::Normal => EdgeKind::Inverted,
+            EdgeKind::Inverted => EdgeKind::Normal,
+        };
     }
 
     pub fn is_inverted(&self) -> bool {
@@ -524,6 +559,8 @@ pub struct Edge {
     pub kind: EdgeKind,
     pub min_length: u32,
     pub weight: i32,
+    /// Edges with false are ignored by ranking.
+    pub constraint: bool,
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -532,7 +569,7 @@ pub enum EdgeKind {
     Inverted,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct NodeId(u32);
 
 impl From<u32> for NodeId {
@@ -547,10 +584,10 @@ impl From<usize> for NodeId {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct EdgeId(u32);
 
-pub const UNEXISTED_EDGE_ID: EdgeId = EdgeId(u32::max_value());
+pub const UNEXISTED_EDGE_ID: EdgeId = EdgeId(u32::MAX);
 
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum Direction {
@@ -570,11 +607,12 @@ impl From<usize> for EdgeId {
     }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Default, PartialEq)]
 pub struct NodeMap<T> {
     values: Vec<T>,
 }
 
+#[derive(Clone, PartialEq)]
 pub struct EdgeMap<T> {
     values: Vec<T>,
 }
@@ -0,0 +1,221 @@
+//! Edge labels. A `label` takes space in the layout like in "A Technique for
+//! Drawing Directed Graphs" (Gansner et al. 1993): ranks are doubled, so
+//! every edge gets a virtual node in the middle, and that node is as high as
+//! the label and wide enough for the edge in its middle and the label on the
+//! right. `headlabel`, `taillabel` and `xlabel` are placed next to the edge
+//! after routing and take no space.
+
+use std::f64::consts::PI;
+
+use crate::graph::*;
+use crate::layout::{Point, RankDir};
+use crate::read_dot::{Attributes, DotGraph};
+use crate::text;
+
+/// Space between an edge and its label.
+pub const LABEL_GAP: f64 = 4.;
+/// Graphviz distance of head and tail labels from the edge ends multiplied by
+/// `labeldistance`.
+const END_LABEL_DISTANCE: f64 = 10.;
+/// Graphviz default `labelangle` in degrees.
+const END_LABEL_ANGLE: f64 = -25.;
+
+/// Sizes of the edge `label`s and their virtual nodes.
+#[derive(Clone, Debug)]
+pub struct EdgeLabels {
+    /// Label sizes of input edges in the top to bottom layout.
+    sizes: EdgeMap<Option<(f64, f64)>>,
+    /// Label nodes in the middle of the split edges.
+    nodes: Vec<(NodeId, (f64, f64))>,
+}
+
+impl EdgeLabels {
+    /// Measures edge labels, sizes are turned from `rankdir` to the top to
+    /// bottom layout.
+    pub fn new(dot: &DotGraph, rankdir: RankDir) -> Self {
+        let mut sizes = EdgeMap::new(0);
+        for (id, attributes) in dot.edge_attributes.iter() {
+            let size = text_size(attributes, "label").map(|(width, height)| {
+                if rankdir.is_horizontal() {
+                    (height, width)
+                } else {
+                    (width, height)
+                }
+            });
+            sizes.set(id, size);
+        }
+        EdgeLabels {
+            sizes,
+            nodes: vec![],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sizes.find_first(Option::is_some).is_none()
+    }
+
+    /// Doubles minimal lengths of all edges, so edges going to the next rank
+    /// get a virtual node. Must be called before ranking.
+    pub fn double_ranks<T>(&self, graph: &mut DirectedGraph<T>) {
+        if self.is_empty() {
+            return;
+        }
+        for n in 0..graph.edges_count() {
+            graph.edge_mut(EdgeId::from(n)).min_length *= 2;
+        }
+    }
+
+    /// Takes the middle virtual node of every labeled edge for its label,
+    /// must be called after [`crate::add_virtual_nodes::add_virtual_nodes`].
+    /// Edges without virtual nodes have no label node.
+    pub fn add_virtual_nodes<T>(&mut self, graph: &DirectedGraph<T>) {
+        for (edge_id, size) in self.sizes.iter() {
+            let Some(size) = *size else {
+                continue;
+            };
+            let mut chain = vec![];
+            let mut to = graph.edge(edge_id).to;
+            while graph.node(to).is_virtual {
+                chain.push(to);
+                to = graph.edge(graph.node(to).outputs[0]).to;
+            }
+            if !chain.is_empty() {
+                self.nodes.push((chain[chain.len() / 2], size));
+            }
+        }
+    }
+
+    /// Label nodes are twice as wide as their labels with gaps, the edge goes
+    /// through the middle and the label is in the right half.
+    pub fn set_sizes(&self, sizes: &mut NodeMap<(f64, f64)>) {
+        for &(id, (width, height)) in &self.nodes {
+            sizes.set(id, ((width + LABEL_GAP) * 2., height));
+        }
+    }
+}
+
+/// Attributes for the font of a label, head and tail labels use
+/// `labelfontname` and `labelfontsize` if set.
+pub fn font_attributes<'a>(attributes: &Attributes<'a>, name: &str) -> Attributes<'a> {
+    let mut font = Attributes::new();
+    for key in ["fontname", "fontsize"] {
+        let end_key = format!("label{key}");
+        let value = Some(attributes.get(end_key.as_str()))
+            .filter(|_| name == "headlabel" || name == "taillabel")
+            .flatten()
+            .or_else(|| attributes.get(key));
+        if let Some(value) = value {
+            font.insert(key.into(), value.clone());
+        }
+    }
+    font
+}
+
+/// Size of the label text of the attribute `name`, None if there is no label.
+pub fn text_size(attributes: &Attributes, name: &str) -> Option<(f64, f64)> {
+    let label = attributes.get(name).filter(|l| !l.is_empty())?;
+    let (font, size) = text::font(&font_attributes(attributes, name));
+    Some(text::text_size(label, font, size))
+}
+
+/// [`text_size`] turned from `rankdir` to the top to bottom layout.
+pub fn frame_text_size(
+    attributes: &Attributes,
+    name: &str,
+    rankdir: RankDir,
+) -> Option<(f64, f64)> {
+    text_size(attributes, name).map(|(width, height)| {
+        if rankdir.is_horizontal() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    })
+}
+
+/// Center of a head or tail label of the given size at the edge `end`, the
+/// edge leaves the node to `next`. Labels are at `labeldistance` and
+/// `labelangle` counterclockwise from the edge.
+pub fn end_label_center(
+    attributes: &Attributes,
+    end: Point,
+    next: Point,
+    (width, height): (f64, f64),
+) -> Point {
+    let number = |name: &str, default: f64| {
+        attributes
+            .get(name)
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .filter(|v| v.is_finite())
+            .unwrap_or(default)
+    };
+    let distance = number("labeldistance", 1.) * END_LABEL_DISTANCE;
+    let angle = number("labelangle", END_LABEL_ANGLE) * PI / 180.;
+    let (dx, dy) = (next.0 - end.0, next.1 - end.1);
+    let length = dx.hypot(dy);
+    let (dx, dy) = if length > 0. {
+        (dx / length, dy / length)
+    } else {
+        (0., 1.)
+    };
+    // The y axis goes down, so counterclockwise is a negative angle.
+    let (sin, cos) = (-angle).sin_cos();
+    let direction = (dx * cos - dy * sin, dx * sin + dy * cos);
+    // The label is moved away, so its box does not cover the end.
+    let offset = distance + (width * direction.0.abs() + height * direction.1.abs()) / 2.;
+    (end.0 + direction.0 * offset, end.1 + direction.1 * offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_dot::parse;
+
+    #[test]
+    fn label_nodes() {
+        let dot = parse("digraph { a -> b [label=x]; a -> c; b -> c [taillabel=y] }").unwrap();
+        let mut graph = dot.graph.clone();
+        let labels = EdgeLabels::new(&dot, RankDir::TopToBottom);
+        assert!(!labels.is_empty());
+        assert!(labels.sizes.get(EdgeId::from(1u32)).is_none());
+        labels.double_ranks(&mut graph);
+        assert_eq!(graph.edge(EdgeId::from(0u32)).min_length, 2);
+
+        let dot = parse("digraph { a -> b [headlabel=x] }").unwrap();
+        assert!(EdgeLabels::new(&dot, RankDir::TopToBottom).is_empty());
+    }
+
+    #[test]
+    fn fonts() {
+        let dot = parse(
+            "digraph { a -> b [label=x, fontsize=10, labelfontsize=20, headlabel=x, xlabel=x] }",
+        )
+        .unwrap();
+        let attributes = dot.edge_attributes.get(EdgeId::from(0u32));
+        let (label, head, xlabel) = (
+            text_size(attributes, "label").unwrap(),
+            text_size(attributes, "headlabel").unwrap(),
+            text_size(attributes, "xlabel").unwrap(),
+        );
+        assert_eq!(label, xlabel);
+        assert_eq!((head.0 / label.0, head.1 / label.1), (2., 2.));
+        assert_eq!(text_size(attributes, "taillabel"), None);
+    }
+
+    #[test]
+    fn end_labels() {
+        let attributes = Attributes::new();
+        // The edge goes down, the default angle turns the label clockwise to
+        // the left of the edge.
+        let (x, y) = end_label_center(&attributes, (0., 0.), (0., 10.), (0., 0.));
+        let angle = END_LABEL_ANGLE.to_radians();
+        assert!((x - 10. * angle.sin()).abs() < 1e-9, "{}", x);
+        assert!((y - 10. * angle.cos()).abs() < 1e-9, "{}", y);
+        assert!(x < 0.);
+
+        // The label center is further by half of its size.
+        let attributes = std::iter::once(("labelangle".into(), "0".into())).collect();
+        let (x, y) = end_label_center(&attributes, (0., 0.), (10., 0.), (10., 4.));
+        assert!((x - 15.).abs() < 1e-9 && y.abs() < 1e-9, "{} {}", x, y);
+    }
+}
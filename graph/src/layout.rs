@@ -0,0 +1,1644 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::cluster::{Cluster, Clusters};
+use crate::flat_edge;
+use crate::graph::*;
+use crate::label;
+use crate::multi_edge::{self, MergedEdge};
+use crate::ortho::{self, OrthoEdge};
+use crate::port::Ports;
+use crate::read_dot::{Attributes, DotGraph};
+use crate::record;
+use crate::self_loop;
+use crate::shape::{self, Shape};
+use crate::spline;
+use crate::text;
+
+/// Point in points, the y axis goes down like in SVG.
+pub type Point = (f64, f64);
+
+/// Rectangle with the top left corner at `(x, y)`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    pub fn center(&self) -> Point {
+        (self.x + self.width / 2., self.y + self.height / 2.)
+    }
+
+    pub fn right(&self) -> f64 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> f64 {
+        self.y + self.height
+    }
+}
+
+/// Direction of ranks, `rankdir` in Graphviz.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RankDir {
+    #[default]
+    TopToBottom,
+    LeftToRight,
+    BottomToTop,
+    RightToLeft,
+}
+
+impl RankDir {
+    pub fn is_horizontal(self) -> bool {
+        matches!(self, RankDir::LeftToRight | RankDir::RightToLeft)
+    }
+
+    /// Turns a point of the top to bottom layout of the given size to this
+    /// direction.
+    pub fn orient_point(self, (x, y): Point, (_, height): (f64, f64)) -> Point {
+        match self {
+            RankDir::TopToBottom => (x, y),
+            RankDir::LeftToRight => (y, x),
+            RankDir::BottomToTop => (x, height - y),
+            RankDir::RightToLeft => (height - y, x),
+        }
+    }
+
+    /// Inverse of [`RankDir::orient_point`].
+    pub fn to_frame(self, (x, y): Point, (_, height): (f64, f64)) -> Point {
+        match self {
+            RankDir::TopToBottom => (x, y),
+            RankDir::LeftToRight => (y, x),
+            RankDir::BottomToTop => (x, height - y),
+            RankDir::RightToLeft => (y, height - x),
+        }
+    }
+
+    fn orient_rect(self, rect: Rect, size: (f64, f64)) -> Rect {
+        let (x1, y1) = self.orient_point((rect.x, rect.y), size);
+        let (x2, y2) = self.orient_point((rect.right(), rect.bottom()), size);
+        Rect {
+            x: x1.min(x2),
+            y: y1.min(y2),
+            width: (x2 - x1).abs(),
+            height: (y2 - y1).abs(),
+        }
+    }
+}
+
+/// How edges are drawn, `splines` in Graphviz.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Splines {
+    /// Smooth curves around nodes.
+    #[default]
+    Spline,
+    /// Straight segments around nodes.
+    Polyline,
+    /// Straight lines from the tail to the head which may cross nodes.
+    Line,
+    /// Horizontal and vertical segments around nodes.
+    Ortho,
+}
+
+/// How parallel edges between the same nodes are drawn, see
+/// [`crate::multi_edge`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MultiEdges {
+    /// Separate edges with different curvatures.
+    #[default]
+    Fan,
+    /// One edge with the number of edges in its label, `concentrate=true` in
+    /// Graphviz.
+    Merge,
+}
+
+/// How back edges are drawn, see [`EdgeLayout::back`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BackEdges {
+    /// Like other edges.
+    #[default]
+    Plain,
+    /// Dashed and red, so cycles stand out.
+    Highlight,
+}
+
+/// Parameters of [`crate::layout`], distances are in points.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayoutOptions {
+    /// Minimal space between nodes of the same rank.
+    pub nodesep: f64,
+    /// Space between bottoms of nodes of a rank and tops of the next one.
+    pub ranksep: f64,
+    /// Minimal node size, nodes grow to fit their labels.
+    pub node_width: f64,
+    pub node_height: f64,
+    /// Passes of the crossing minimization.
+    pub iterations: u32,
+    /// Shuffles nodes of every rank before the crossing minimization, None
+    /// keeps the input order.
+    pub seed: Option<u64>,
+    /// Space around the drawing on the left and right, top and bottom sides.
+    pub margin: (f64, f64),
+    pub rankdir: RankDir,
+    pub splines: Splines,
+    pub multi_edges: MultiEdges,
+    pub back_edges: BackEdges,
+}
+
+/// Largest accepted `mclimit`, bigger values would make the ordering run
+/// for ages.
+pub const MAX_MCLIMIT: f64 = 100.;
+/// Largest accepted separation, margin or node size in inches, bigger values
+/// overflow integer coordinates.
+pub const MAX_INCHES: f64 = 100.;
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        Self {
+            nodesep: 10.,
+            ranksep: 50.,
+            node_width: 40.,
+            node_height: 20.,
+            iterations: 24,
+            seed: None,
+            margin: (0., 0.),
+            rankdir: RankDir::TopToBottom,
+            splines: Splines::Spline,
+            multi_edges: MultiEdges::Fan,
+            back_edges: BackEdges::Plain,
+        }
+    }
+}
+
+impl LayoutOptions {
+    pub fn from_attributes(attributes: &Attributes) -> Self {
+        let mut options = Self::default();
+        for (name, value) in attributes {
+            options.set(name, value);
+        }
+        options
+    }
+
+    /// Sets an option from a Graphviz graph attribute: `nodesep`, `ranksep`
+    /// and `margin` are in inches up to [`MAX_INCHES`], `mclimit` scales the
+    /// number of iterations up to [`MAX_MCLIMIT`] times, `start` ends with
+    /// the seed, `rankdir` is one of `TB`, `LR`, `BT` and `RL`, `splines` is
+    /// one of `spline`, `true`, `polyline`, `line`, `false` and `ortho`,
+    /// `concentrate=true` merges parallel edges and `backedges` is `plain`
+    /// or `highlight`. Other attributes and invalid values are ignored.
+    pub fn set(&mut self, name: &str, value: &str) {
+        let inches = |value: &str| {
+            value
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|v| (0. ..=MAX_INCHES).contains(v))
+                .map(|v| v * 72.)
+        };
+        match name {
+            "nodesep" => self.nodesep = inches(value).unwrap_or(self.nodesep),
+            // Graphviz allows `equally` after the value.
+            "ranksep" => {
+                let value = value.split_whitespace().next().unwrap_or_default();
+                self.ranksep = inches(value).unwrap_or(self.ranksep);
+            }
+            "margin" => {
+                let (x, y) = value.split_once(',').unwrap_or((value, value));
+                if let (Some(x), Some(y)) = (inches(x), inches(y)) {
+                    self.margin = (x, y);
+                }
+            }
+            "mclimit" => {
+                let scale = value.trim().parse::<f64>().ok();
+                if let Some(scale) = scale.filter(|v| (0. ..=MAX_MCLIMIT).contains(v)) {
+                    self.iterations = (Self::default().iterations as f64 * scale).round() as u32;
+                }
+            }
+            "start" => {
+                let digits = value.trim_start_matches(|c: char| !c.is_ascii_digit());
+                self.seed = digits.parse().ok().or(self.seed);
+            }
+            "rankdir" => {
+                self.rankdir = match value.to_ascii_uppercase().as_str() {
+                    "TB" => RankDir::TopToBottom,
+                    "LR" => RankDir::LeftToRight,
+                    "BT" => RankDir::BottomToTop,
+                    "RL" => RankDir::RightToLeft,
+                    _ => self.rankdir,
+                }
+            }
+            "splines" => {
+                self.splines = match value.to_ascii_lowercase().as_str() {
+                    "spline" | "true" | "curved" | "compound" => Splines::Spline,
+                    "polyline" => Splines::Polyline,
+                    "line" | "false" => Splines::Line,
+                    "ortho" => Splines::Ortho,
+                    _ => self.splines,
+                }
+            }
+            "concentrate" => {
+                self.multi_edges = match value.to_ascii_lowercase().as_str() {
+                    "true" => MultiEdges::Merge,
+                    "false" => MultiEdges::Fan,
+                    _ => self.multi_edges,
+                }
+            }
+            "backedges" => {
+                self.back_edges = match value.to_ascii_lowercase().as_str() {
+                    "plain" => BackEdges::Plain,
+                    "highlight" => BackEdges::Highlight,
+                    _ => self.back_edges,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Options of the top to bottom layout which is turned to `rankdir` at
+    /// the end, node sizes and margins are swapped for horizontal directions.
+    pub(crate) fn frame(&self) -> Self {
+        let mut frame = self.clone();
+        if self.rankdir.is_horizontal() {
+            (frame.node_width, frame.node_height) = (self.node_height, self.node_width);
+            frame.margin = (self.margin.1, self.margin.0);
+        }
+        frame
+    }
+}
+
+/// Geometry of the laid out graph, node and edge ids are the ones of the
+/// input graph.
+#[derive(Clone, Debug)]
+pub struct Layout {
+    /// Boxes of the input nodes.
+    pub nodes: NodeMap<Rect>,
+    /// Input edges, drawn from the tail to the head.
+    pub edges: EdgeMap<EdgeLayout>,
+    /// Loops in [`DirectedGraph::iter_self_edges`] order.
+    pub self_edges: Vec<EdgeLayout>,
+    /// Boxes of clusters in [`Clusters`] order, None for clusters without
+    /// nodes.
+    pub clusters: Vec<Option<Rect>>,
+    /// Coordinates of rank centers along the rank direction, y for vertical
+    /// directions and x for horizontal ones.
+    pub ranks: Vec<f64>,
+    pub bounding_box: Rect,
+    pub rankdir: RankDir,
+}
+
+/// Edge as a smooth chain of cubic Bezier curves.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EdgeLayout {
+    /// Control points from the tail to the head, `3n + 1` of them.
+    pub points: Vec<Point>,
+    /// Tip of the arrow at the head, the curve stops 1 point before the node
+    /// side to leave space for it.
+    pub arrow: Option<Point>,
+    /// Centers of the `label`, `headlabel`, `taillabel` and `xlabel` texts.
+    pub label: Option<Point>,
+    pub head_label: Option<Point>,
+    pub tail_label: Option<Point>,
+    pub xlabel: Option<Point>,
+    /// Number of input edges drawn as this one, more than 1 if parallel
+    /// edges are merged and 0 for edges merged into another one.
+    pub count: u32,
+    /// The edge goes against the rank direction, it was reversed to break a
+    /// cycle or by rank constraints. Points still go from the tail to the
+    /// head.
+    pub back: bool,
+}
+
+impl EdgeLayout {
+    /// Ends of the curves.
+    pub fn polyline(&self) -> impl Iterator<Item = Point> + '_ {
+        self.points.iter().step_by(3).copied()
+    }
+
+    /// Control points, the arrow tip and label centers.
+    fn points_mut(&mut self) -> impl Iterator<Item = &mut Point> {
+        self.points
+            .iter_mut()
+            .chain(&mut self.arrow)
+            .chain(&mut self.label)
+            .chain(&mut self.head_label)
+            .chain(&mut self.tail_label)
+            .chain(&mut self.xlabel)
+    }
+}
+
+impl Layout {
+    /// Converts ranks and x coordinates of node centers of the graph with
+    /// virtual nodes to points.
+    pub(crate) fn new(
+        dot: &DotGraph,
+        ranks: &NodeMap<i32>,
+        xs: &NodeMap<u32>,
+        sizes: &NodeMap<(f64, f64)>,
+        clusters: &Clusters,
+        ports: &Ports,
+        options: &LayoutOptions,
+    ) -> Self {
+        let graph = &dot.graph;
+        let scale = Scale::new(dot, ranks, xs, sizes, clusters, options);
+        let mut nodes = NodeMap::new(0);
+        for (id, node) in graph.iter_nodes_with_id() {
+            if !node.is_virtual {
+                nodes.set(id, scale.node_box(id));
+            }
+        }
+
+        let edges_count = dot.edge_attributes.iter_ids().count() as u32;
+        let routes: Vec<_> = (0..edges_count)
+            .map(|n| EdgeRoute::new(dot, &scale, ports, EdgeId::from(n)))
+            .collect();
+        let ortho = (options.splines == Splines::Ortho).then(|| ortho_paths(&scale, &routes));
+        let fan = fan_offsets(&routes);
+        let mut edges = EdgeMap::new(edges_count);
+        for (n, ((_, edge), route)) in edges.iter_mut().zip(&routes).enumerate() {
+            let (start, end) = (route.start, route.end);
+            let attributes = dot.edge_attributes.get(route.edge_id);
+            let points = match (options.splines, route.arc) {
+                (Splines::Spline, Some(top)) => flat_edge::arc(start, end, top),
+                (Splines::Polyline | Splines::Ortho, Some(top)) => {
+                    straight(&flat_edge::arc_polyline(start, end, top))
+                }
+                (splines, _) => match splines {
+                    Splines::Spline if fan[n] != 0. => multi_edge::fan(start, end, fan[n]),
+                    Splines::Spline => spline::route(start, end, &route.boxes),
+                    Splines::Polyline if fan[n] != 0. => {
+                        straight(&[start, multi_edge::fan_middle(start, end, fan[n]), end])
+                    }
+                    Splines::Polyline => straight(&spline::shortest_path(start, end, &route.boxes)),
+                    Splines::Line => straight(&multi_edge::parallel(start, end, fan[n])),
+                    // Ortho edges go down the ranks, positive offsets move
+                    // them to the left like fanned curves.
+                    Splines::Ortho => {
+                        let path = ortho.as_ref().unwrap()[n].iter();
+                        straight(&path.map(|&(x, y)| (x - fan[n], y)).collect::<Vec<_>>())
+                    }
+                },
+            };
+            *edge = edge_layout(dot, &scale, route, points);
+            edge.label = label_node_center(&scale, route).or_else(|| {
+                let top = route.arc?;
+                let size = label::frame_text_size(attributes, "label", options.rankdir)?;
+                Some(flat_edge::label_center(start, end, top, size))
+            });
+            place_labels(attributes, options, edge);
+        }
+
+        let loops = self_loop::self_loops(dot, options.rankdir);
+        let mut self_edges = vec![];
+        for (self_loop, attributes) in loops.iter().zip(&dot.self_edge_attributes) {
+            let node = scale.node_box(self_loop.node);
+            let mut points = self_loop.points(&node);
+            let inside = |p| contains(dot, &scale, self_loop.node, p);
+            clip(&mut points, inside);
+            points.reverse();
+            clip(&mut points, inside);
+            points.reverse();
+            let mut edge = with_arrow(dot, points);
+            edge.label = self_loop.label_center(&node);
+            place_labels(attributes, options, &mut edge);
+            self_edges.push(edge);
+        }
+
+        let loop_widths = self_loop::loop_widths(dot, options.rankdir);
+        let mut layout = Layout {
+            nodes,
+            edges,
+            self_edges,
+            clusters: cluster_boxes(graph, &scale, clusters, &loop_widths, options.rankdir),
+            ranks: scale.rank_centers.clone(),
+            bounding_box: Rect::default(),
+            rankdir: RankDir::TopToBottom,
+        };
+        layout.fit(dot, &loop_widths, options);
+        layout
+    }
+
+    /// Moves everything which is outside of the margins in and sets the
+    /// bounding box around nodes with their loops, clusters, edges and
+    /// labels.
+    fn fit(&mut self, dot: &DotGraph, loop_widths: &NodeMap<f64>, options: &LayoutOptions) {
+        let mut rects: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|(id, node)| Rect {
+                width: node.width + loop_widths.get(id),
+                ..*node
+            })
+            .chain(self.clusters.iter().flatten().copied())
+            .collect();
+        let edges = self
+            .edges
+            .iter()
+            .map(|(id, edge)| (edge, dot.edge_attributes.get(id)));
+        for (edge, attributes) in edges.chain(self.self_edges.iter().zip(&dot.self_edge_attributes))
+        {
+            let points = edge.points.iter().chain(&edge.arrow);
+            rects.extend(points.map(|&(x, y)| Rect {
+                x,
+                y,
+                width: 0.,
+                height: 0.,
+            }));
+            let labels = [
+                ("label", edge.label),
+                ("headlabel", edge.head_label),
+                ("taillabel", edge.tail_label),
+                ("xlabel", edge.xlabel),
+            ];
+            for (name, center) in labels {
+                let size = label::frame_text_size(attributes, name, options.rankdir);
+                if let (Some((x, y)), Some((width, height))) = (center, size) {
+                    rects.push(Rect {
+                        x: x - width / 2.,
+                        y: y - height / 2.,
+                        width,
+                        height,
+                    });
+                }
+            }
+        }
+
+        let (margin_x, margin_y) = options.margin;
+        let (left, top, right, bottom) =
+            rects
+                .iter()
+                .fold((margin_x, margin_y, 0., 0.), |(l, t, r, b), rect| {
+                    (
+                        l.min(rect.x),
+                        t.min(rect.y),
+                        f64::max(r, rect.right()),
+                        f64::max(b, rect.bottom()),
+                    )
+                });
+        let shift = (margin_x - left, margin_y - top);
+        if shift != (0., 0.) {
+            self.translate(shift);
+        }
+        self.bounding_box = Rect {
+            x: 0.,
+            y: 0.,
+            width: right + shift.0 + margin_x,
+            height: bottom + shift.1 + margin_y,
+        };
+    }
+
+    fn translate(&mut self, (dx, dy): Point) {
+        let rects = self.nodes.iter_mut().map(|(_, rect)| rect);
+        for rect in rects.chain(self.clusters.iter_mut().flatten()) {
+            (rect.x, rect.y) = (rect.x + dx, rect.y + dy);
+        }
+        let edges = self.edges.iter_mut().map(|(_, edge)| edge);
+        for edge in edges.chain(&mut self.self_edges) {
+            for point in edge.points_mut() {
+                *point = (point.0 + dx, point.1 + dy);
+            }
+        }
+        for rank in &mut self.ranks {
+            *rank += dy;
+        }
+    }
+
+    /// Layout of the input edges from the layout of the graph with merged
+    /// parallel edges, see [`crate::multi_edge::merge`]. Edges merged into
+    /// another one share its points but have no labels.
+    pub(crate) fn unmerge(mut self, merged: &EdgeMap<MergedEdge>) -> Self {
+        let mut edges = EdgeMap::new(0);
+        for (id, merged) in merged.iter() {
+            let mut edge = self.edges.get(merged.edge).clone();
+            edge.count = merged.count;
+            if merged.count == 0 {
+                (edge.label, edge.head_label, edge.tail_label, edge.xlabel) =
+                    (None, None, None, None);
+            }
+            edges.set(id, edge);
+        }
+        self.edges = edges;
+        self
+    }
+
+    /// Turns the top to bottom layout to `rankdir`.
+    pub(crate) fn orient(mut self, rankdir: RankDir) -> Self {
+        let size = (self.bounding_box.width, self.bounding_box.height);
+        for (_, rect) in self.nodes.iter_mut() {
+            *rect = rankdir.orient_rect(*rect, size);
+        }
+        let edges = self.edges.iter_mut().map(|(_, edge)| edge);
+        for edge in edges.chain(&mut self.self_edges) {
+            for point in edge.points_mut() {
+                *point = rankdir.orient_point(*point, size);
+            }
+        }
+        for rect in self.clusters.iter_mut().flatten() {
+            *rect = rankdir.orient_rect(*rect, size);
+        }
+        for rank in &mut self.ranks {
+            let (x, y) = rankdir.orient_point((0., *rank), size);
+            *rank = if rankdir.is_horizontal() { x } else { y };
+        }
+        self.bounding_box = rankdir.orient_rect(self.bounding_box, size);
+        self.rankdir = rankdir;
+        self
+    }
+}
+
+/// Size of a node in whole points: its label with margins, but not less than
+/// the `width` and `height` attributes in inches or the default node size.
+/// Records fit their fields, other shapes are sized by [`Shape::size`].
+pub fn node_size(dot: &DotGraph, id: NodeId, options: &LayoutOptions) -> (f64, f64) {
+    let attributes = dot.node_attributes.get(id);
+    let label = dot
+        .label(id)
+        .or_else(|| dot.graph.original_id(id).map(|v| v.as_ref()))
+        .unwrap_or_default();
+    let horizontal = !options.rankdir.is_horizontal();
+    let shape = Shape::from_attributes(attributes);
+    let (width, height) = match record::node_fields(attributes, label, horizontal) {
+        Some((_, size)) => size,
+        None => {
+            let (font, size) = text::font(attributes);
+            shape.size(text::text_size(label, font, size), record::FIELD_MARGIN)
+        }
+    };
+    let (default_width, default_height) = if shape == Shape::Point {
+        (shape::POINT_SIZE, shape::POINT_SIZE)
+    } else {
+        (options.node_width, options.node_height)
+    };
+    let inches = |name: &str, default: f64| {
+        attributes
+            .get(name)
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .filter(|v| *v > 0. && *v <= MAX_INCHES)
+            .map_or(default, |v| v * 72.)
+    };
+    let width = width.max(inches("width", default_width)).ceil();
+    let height = height.max(inches("height", default_height)).ceil();
+    if shape.is_square(attributes) {
+        (width.max(height), width.max(height))
+    } else {
+        (width, height)
+    }
+}
+
+/// Sizes of nodes of the graph with virtual nodes in the top to bottom frame,
+/// virtual nodes have the default width and no height.
+pub fn node_sizes(dot: &DotGraph, options: &LayoutOptions) -> NodeMap<(f64, f64)> {
+    let frame = options.frame();
+    let mut sizes = dot.graph.node_map();
+    for (id, node) in dot.graph.iter_nodes_with_id() {
+        let size = if node.is_virtual {
+            (frame.node_width, 0.)
+        } else {
+            let (width, height) = node_size(dot, id, options);
+            if options.rankdir.is_horizontal() {
+                (height, width)
+            } else {
+                (width, height)
+            }
+        };
+        sizes.set(id, size);
+    }
+    sizes
+}
+
+/// Space between a cluster border and its nodes or nested clusters.
+const CLUSTER_PADDING: f64 = 6.;
+
+/// Converts ranks and x coordinates to points, every rank is as high as its
+/// highest node.
+struct Scale<'a> {
+    ranks: &'a NodeMap<i32>,
+    xs: &'a NodeMap<u32>,
+    sizes: &'a NodeMap<(f64, f64)>,
+    rank_centers: Vec<f64>,
+    rank_heights: Vec<f64>,
+    /// Nodes of every rank from left to right.
+    layers: Vec<Vec<NodeId>>,
+    /// Moves the leftmost node side to the margin.
+    shift: f64,
+    /// Leftmost and rightmost node sides.
+    sides: (f64, f64),
+    /// Flat edges drawn as arcs above their rank, see [`flat_edge`].
+    arcs: HashSet<EdgeId>,
+    options: &'a LayoutOptions,
+}
+
+impl<'a> Scale<'a> {
+    fn new(
+        dot: &DotGraph,
+        ranks: &'a NodeMap<i32>,
+        xs: &'a NodeMap<u32>,
+        sizes: &'a NodeMap<(f64, f64)>,
+        clusters: &Clusters,
+        options: &'a LayoutOptions,
+    ) -> Self {
+        let graph = &dot.graph;
+        let mut heights = vec![];
+        let mut layers = vec![];
+        // First and last ranks of every cluster.
+        let mut cluster_ranks = vec![(usize::MAX, 0); clusters.clusters.len()];
+        for id in graph.iter_nodes_ids() {
+            let rank = *ranks.get(id) as usize;
+            if heights.len() <= rank {
+                heights.resize(rank + 1, 0.);
+                layers.resize(rank + 1, vec![]);
+            }
+            heights[rank] = f64::max(heights[rank], sizes.get(id).1);
+            layers[rank].push(id);
+            for cluster in clusters.path(id) {
+                let (first, last) = &mut cluster_ranks[cluster];
+                (*first, *last) = ((*first).min(rank), (*last).max(rank));
+            }
+        }
+        for layer in &mut layers {
+            layer.sort_by_key(|&id| *xs.get(id));
+        }
+
+        // Cluster borders take the space on the left of the nodes and above
+        // and below the ranks where clusters start and end.
+        let paddings: Vec<_> = clusters
+            .clusters
+            .iter()
+            .map(|cluster| cluster_padding(cluster, options.rankdir))
+            .collect();
+        let mut shift = f64::MIN;
+        let mut borders_above = vec![0.; heights.len()];
+        let mut borders_below = vec![0.; heights.len()];
+        for id in graph.iter_nodes_ids() {
+            let rank = *ranks.get(id) as usize;
+            let (mut left, mut top, mut bottom) = (0., 0., 0.);
+            for cluster in clusters.path(id) {
+                let [cluster_left, cluster_top, _, cluster_bottom] = paddings[cluster];
+                left += cluster_left;
+                if cluster_ranks[cluster].0 == rank {
+                    top += cluster_top;
+                }
+                if cluster_ranks[cluster].1 == rank {
+                    bottom += cluster_bottom;
+                }
+            }
+            borders_above[rank] = f64::max(borders_above[rank], top);
+            borders_below[rank] = f64::max(borders_below[rank], bottom);
+            shift = shift.max(sizes.get(id).0 / 2. + left - *xs.get(id) as f64);
+        }
+
+        let arcs = flat_arcs(dot, ranks, &layers);
+        let mut above = vec![0.; heights.len()];
+        for &id in &arcs {
+            let rank = *ranks.get(graph.edge(id).from) as usize;
+            let label =
+                label::frame_text_size(dot.edge_attributes.get(id), "label", options.rankdir);
+            above[rank] = f64::max(above[rank], flat_edge::space_above(label.map(|(_, h)| h)));
+        }
+        let mut rank_centers = vec![];
+        let mut bottom = options.margin.1;
+        for (rank, height) in heights.iter().enumerate() {
+            // Arcs take the space between ranks first, above the first rank
+            // they are added to the margin. Cluster borders are added to
+            // the gap.
+            let gap = if rank == 0 {
+                above[0] + borders_above[0]
+            } else {
+                options.ranksep.max(above[rank]) + borders_below[rank - 1] + borders_above[rank]
+            };
+            rank_centers.push(bottom + gap + height / 2.);
+            bottom += gap + height;
+        }
+        let mut scale = Scale {
+            ranks,
+            xs,
+            sizes,
+            rank_centers,
+            rank_heights: heights,
+            layers,
+            shift: options.margin.0 + shift.max(0.),
+            sides: (0., 0.),
+            arcs,
+            options,
+        };
+        scale.sides = graph
+            .iter_nodes_ids()
+            .map(|id| scale.node_box(id))
+            .fold((f64::MAX, f64::MIN), |(l, r), node| {
+                (l.min(node.x), r.max(node.right()))
+            });
+        scale
+    }
+
+    /// Top of the arc of a flat edge, None for other edges.
+    fn arc_top(&self, edge_id: EdgeId, tail: NodeId) -> Option<f64> {
+        self.arcs
+            .contains(&edge_id)
+            .then(|| self.rank_span(tail).0 - flat_edge::ARC_HEIGHT)
+    }
+
+    /// Top and bottom of the rank of the node.
+    fn rank_span(&self, id: NodeId) -> (f64, f64) {
+        let rank = *self.ranks.get(id) as usize;
+        let (center, height) = (self.rank_centers[rank], self.rank_heights[rank]);
+        (center - height / 2., center + height / 2.)
+    }
+
+    /// Boxes an edge may go through from top to bottom: the tail below
+    /// `start`, the space between ranks, the space between neighbors of every
+    /// virtual node and the head above `end`. Edges keep to the middle
+    /// between neighbor virtual nodes and to the left of their labels.
+    fn corridor(
+        &self,
+        tail: NodeId,
+        virtual_nodes: &[NodeId],
+        head: NodeId,
+        start: Point,
+        end: Point,
+    ) -> Vec<Rect> {
+        let (left, right) = self.sides;
+        let rect = |x1: f64, y1: f64, x2: f64, y2: f64| Rect {
+            x: x1,
+            y: y1,
+            width: x2 - x1,
+            height: (y2 - y1).max(0.),
+        };
+        let tail_box = self.node_box(tail);
+        let mut bottom = self.rank_span(tail).1;
+        let mut boxes = vec![rect(tail_box.x, start.1, tail_box.right(), bottom)];
+        for &id in virtual_nodes {
+            let (top, next_bottom) = self.rank_span(id);
+            boxes.push(rect(left, bottom, right, top));
+            let x = self.node_box(id).center().0;
+            // The edge goes on the left of its label.
+            let is_label = self.sizes.get(id).1 > 0.;
+            let layer = &self.layers[*self.ranks.get(id) as usize];
+            let place = layer.iter().position(|&n| n == id).unwrap();
+            let side = |neighbor: Option<&NodeId>, is_left: bool| {
+                let Some(&neighbor) = neighbor else {
+                    return if is_left { left } else { right };
+                };
+                let node = self.node_box(neighbor);
+                // Only virtual nodes have no height.
+                if self.sizes.get(neighbor).1 == 0. {
+                    (node.center().0 + x) / 2.
+                } else if is_left {
+                    node.right().min(x)
+                } else {
+                    node.x.max(x)
+                }
+            };
+            let neighbors = (
+                place.checked_sub(1).map(|n| &layer[n]),
+                layer.get(place + 1),
+            );
+            boxes.push(rect(
+                side(neighbors.0, true),
+                top,
+                if is_label {
+                    x
+                } else {
+                    side(neighbors.1, false)
+                },
+                next_bottom,
+            ));
+            bottom = next_bottom;
+        }
+        let head_box = self.node_box(head);
+        let top = self.rank_span(head).0;
+        boxes.push(rect(left, bottom, right, top));
+        boxes.push(rect(head_box.x, top, head_box.right(), end.1));
+        boxes
+    }
+
+    fn node_box(&self, id: NodeId) -> Rect {
+        let (width, height) = *self.sizes.get(id);
+        Rect {
+            x: self.shift + *self.xs.get(id) as f64 - width / 2.,
+            y: self.rank_centers[*self.ranks.get(id) as usize] - height / 2.,
+            width,
+            height,
+        }
+    }
+}
+
+/// Flat edges which are drawn as arcs: edges with labels and edges between
+/// nodes which are not neighbors.
+fn flat_arcs(dot: &DotGraph, ranks: &NodeMap<i32>, layers: &[Vec<NodeId>]) -> HashSet<EdgeId> {
+    let graph = &dot.graph;
+    let mut arcs = HashSet::new();
+    for (id, attributes) in dot.edge_attributes.iter() {
+        let edge = graph.edge(id);
+        let rank = *ranks.get(edge.from);
+        if rank != *ranks.get(edge.to) {
+            continue;
+        }
+        let layer = &layers[rank as usize];
+        let place = |node| layer.iter().position(|&n| n == node).unwrap();
+        let has_label = attributes.get("label").is_some_and(|l| !l.is_empty());
+        if has_label || place(edge.from).abs_diff(place(edge.to)) > 1 {
+            arcs.insert(id);
+        }
+    }
+    arcs
+}
+
+/// Paddings of the left, top, right and bottom cluster borders in the top to
+/// bottom layout. The label is drawn at the top of the final layout, so it
+/// takes the side which is turned there by `rankdir`.
+fn cluster_padding(cluster: &Cluster, rankdir: RankDir) -> [f64; 4] {
+    let mut padding = [CLUSTER_PADDING; 4];
+    let label_side = match rankdir {
+        RankDir::TopToBottom => 1,
+        RankDir::BottomToTop => 3,
+        RankDir::LeftToRight | RankDir::RightToLeft => 0,
+    };
+    padding[label_side] += cluster.label_height();
+    padding
+}
+
+/// Bounding boxes of clusters, None for clusters without nodes. A cluster
+/// is padded around its nodes and nested clusters.
+fn cluster_boxes<T>(
+    graph: &DirectedGraph<T>,
+    scale: &Scale,
+    clusters: &Clusters,
+    loop_widths: &NodeMap<f64>,
+    rankdir: RankDir,
+) -> Vec<Option<Rect>> {
+    let extend = |b: &mut Option<(f64, f64, f64, f64)>, (x1, y1, x2, y2)| {
+        let b = b.get_or_insert((x1, y1, x2, y2));
+        *b = (b.0.min(x1), b.1.min(y1), b.2.max(x2), b.3.max(y2));
+    };
+    let mut boxes = vec![None; clusters.clusters.len()];
+    for id in graph.iter_nodes_ids() {
+        let Some(cluster) = clusters.cluster(id) else {
+            continue;
+        };
+        let node = scale.node_box(id);
+        // Virtual nodes are only points in the middle of the node box, label
+        // nodes are whole.
+        let sides = if graph.node(id).is_virtual && node.height == 0. {
+            let (x, y) = node.center();
+            (x, y, x, y)
+        } else {
+            let right = node.right() + loop_widths.get(id);
+            (node.x, node.y, right, node.bottom())
+        };
+        extend(&mut boxes[cluster], sides);
+    }
+    // Nested clusters come after their parents.
+    for index in (0..boxes.len()).rev() {
+        let Some((x1, y1, x2, y2)) = boxes[index] else {
+            continue;
+        };
+        let cluster = &clusters.clusters[index];
+        let [left, top, right, bottom] = cluster_padding(cluster, rankdir);
+        let sides = (x1 - left, y1 - top, x2 + right, y2 + bottom);
+        boxes[index] = Some(sides);
+        if let Some(parent) = cluster.parent {
+            extend(&mut boxes[parent], sides);
+        }
+    }
+    boxes
+        .into_iter()
+        .map(|b| {
+            let (x1, y1, x2, y2) = b?;
+            Some(Rect {
+                x: x1,
+                y: y1,
+                width: x2 - x1,
+                height: y2 - y1,
+            })
+        })
+        .collect()
+}
+
+/// Ends of an edge and the corridor around its virtual nodes.
+struct EdgeRoute {
+    edge_id: EdgeId,
+    tail: NodeId,
+    head: NodeId,
+    /// Ends are at node centers for edges which are clipped by node outlines,
+    /// ports are already on the node sides.
+    start: Point,
+    end: Point,
+    clip_start: bool,
+    clip_end: bool,
+    virtual_nodes: Vec<NodeId>,
+    boxes: Vec<Rect>,
+    /// Top of the arc of a flat edge.
+    arc: Option<f64>,
+}
+
+impl EdgeRoute {
+    fn new(dot: &DotGraph, scale: &Scale, ports: &Ports, edge_id: EdgeId) -> Self {
+        let graph = &dot.graph;
+        let segments = chain(graph, edge_id);
+        let (tail, head) = (
+            graph.edge(segments[0]).from,
+            graph.edge(segments[segments.len() - 1]).to,
+        );
+        let (tail_port, _) = ports.ends(segments[0]);
+        let (_, head_port) = ports.ends(segments[segments.len() - 1]);
+        let end_point = |id: NodeId, port: Option<(f64, f64)>| {
+            let node = scale.node_box(id);
+            port.map_or(node.center(), |(px, py)| {
+                (node.x + px * node.width, node.y + py * node.height)
+            })
+        };
+        let (start, end) = (end_point(tail, tail_port), end_point(head, head_port));
+        let virtual_nodes: Vec<_> = segments[1..]
+            .iter()
+            .map(|&segment| graph.edge(segment).from)
+            .collect();
+        EdgeRoute {
+            edge_id,
+            tail,
+            head,
+            start,
+            end,
+            clip_start: tail_port.is_none(),
+            clip_end: head_port.is_none(),
+            boxes: scale.corridor(tail, &virtual_nodes, head, start, end),
+            virtual_nodes,
+            arc: scale.arc_top(edge_id, tail),
+        }
+    }
+}
+
+/// Clips the routed edge by the node outlines and turns inverted edges back
+/// to the input direction.
+fn edge_layout(dot: &DotGraph, scale: &Scale, route: &EdgeRoute, points: Vec<Point>) -> EdgeLayout {
+    let mut points = points;
+    if route.clip_start {
+        clip(&mut points, |p| contains(dot, scale, route.tail, p));
+    }
+    if route.clip_end {
+        points.reverse();
+        clip(&mut points, |p| contains(dot, scale, route.head, p));
+        points.reverse();
+    }
+    let back = dot.graph.edge(route.edge_id).is_inverted();
+    if back {
+        points.reverse();
+    }
+    EdgeLayout {
+        back,
+        ..with_arrow(dot, points)
+    }
+}
+
+/// Arrows of directed graphs take 1 point at the node side.
+fn with_arrow(dot: &DotGraph, points: Vec<Point>) -> EdgeLayout {
+    let mut points = points;
+    let arrow = dot.directed.then(|| {
+        let tip = points[points.len() - 1];
+        shorten(&mut points);
+        tip
+    });
+    EdgeLayout {
+        points,
+        arrow,
+        count: 1,
+        ..EdgeLayout::default()
+    }
+}
+
+/// Center of the label in the right half of the label node of the edge.
+fn label_node_center(scale: &Scale, route: &EdgeRoute) -> Option<Point> {
+    // Only label nodes are virtual nodes with height.
+    let &id = route
+        .virtual_nodes
+        .iter()
+        .find(|&&id| scale.sizes.get(id).1 > 0.)?;
+    let node = scale.node_box(id);
+    let width = node.width / 2. - label::LABEL_GAP;
+    Some((node.right() - width / 2., node.center().1))
+}
+
+/// Places edge labels in the frame: `label` to the right of the edge middle
+/// unless it is already placed, `xlabel` to the left of the middle and the
+/// others next to the edge ends.
+fn place_labels(attributes: &Attributes, options: &LayoutOptions, edge: &mut EdgeLayout) {
+    let size = |name: &str| label::frame_text_size(attributes, name, options.rankdir);
+    let points = &edge.points;
+    let curves = (points.len() - 1) / 3;
+    let curve = &points[curves / 2 * 3..];
+    let t = if curves % 2 == 1 { 0.5 } else { 0. };
+    let middle = spline::bezier(&[curve[0], curve[1], curve[2], curve[3]], t);
+    let beside = |(width, _): (f64, f64), side: f64| {
+        (middle.0 + side * (label::LABEL_GAP + width / 2.), middle.1)
+    };
+
+    if edge.label.is_none() {
+        edge.label = size("label").map(|size| beside(size, 1.));
+    }
+    edge.xlabel = size("xlabel").map(|size| beside(size, -1.));
+    let count = points.len();
+    let head = edge.arrow.unwrap_or(points[count - 1]);
+    edge.head_label = size("headlabel")
+        .map(|size| label::end_label_center(attributes, head, points[count - 2], size));
+    edge.tail_label = size("taillabel")
+        .map(|size| label::end_label_center(attributes, points[0], points[1], size));
+}
+
+/// Offsets of the middles of parallel edges going to the next rank, which
+/// would be drawn over each other, from the straight line between the ends.
+fn fan_offsets(routes: &[EdgeRoute]) -> Vec<f64> {
+    let mut groups = HashMap::<_, Vec<usize>>::new();
+    for (n, route) in routes.iter().enumerate() {
+        if route.virtual_nodes.is_empty() {
+            let (start, end) = (route.start, route.end);
+            let key = [start.0, start.1, end.0, end.1].map(f64::to_bits);
+            groups.entry(key).or_default().push(n);
+        }
+    }
+    let mut offsets = vec![0.; routes.len()];
+    for group in groups.values() {
+        let middle = (group.len() - 1) as f64 / 2.;
+        for (k, &n) in group.iter().enumerate() {
+            offsets[n] = (k as f64 - middle) * multi_edge::FAN_STEP;
+        }
+    }
+    offsets
+}
+
+/// Polylines of all edges for `splines=ortho`. Verticals through the edge
+/// ends and virtual nodes may move in the middle halves of their corridor
+/// boxes, neighbor verticals which can be aligned are straight.
+fn ortho_paths(scale: &Scale, routes: &[EdgeRoute]) -> Vec<Vec<Point>> {
+    let is_flat = |route: &EdgeRoute| scale.ranks.get(route.tail) == scale.ranks.get(route.head);
+    let edges: Vec<_> = routes
+        .iter()
+        .filter(|route| !is_flat(route))
+        .map(|route| {
+            let last = route.boxes.len() - 1;
+            let range = |index: usize, port: Option<f64>| {
+                let b = route.boxes[index];
+                port.map_or((b.x + b.width / 4., b.right() - b.width / 4.), |x| (x, x))
+            };
+            // Ends and virtual nodes, their boxes go after the space between
+            // ranks.
+            let mut ranges = vec![range(0, (!route.clip_start).then_some(route.start.0))];
+            ranges.extend((0..route.virtual_nodes.len()).map(|n| range(2 + n * 2, None)));
+            ranges.push(range(last, (!route.clip_end).then_some(route.end.0)));
+
+            let mut xs = vec![];
+            let mut run = (0, ranges[0]);
+            for n in 1..=ranges.len() {
+                let common = ranges
+                    .get(n)
+                    .map(|r| (r.0.max(run.1 .0), r.1.min(run.1 .1)))
+                    .filter(|r| r.0 <= r.1);
+                match common {
+                    Some(common) => run.1 = common,
+                    None => {
+                        // The run goes at the middle of its centers.
+                        let centers = (run.0..n).map(|n| (ranges[n].0 + ranges[n].1) / 2.);
+                        let middle = centers.sum::<f64>() / (n - run.0) as f64;
+                        let x = middle.clamp(run.1 .0, run.1 .1);
+                        xs.extend((run.0..n).map(|_| x));
+                        if let Some(&range) = ranges.get(n) {
+                            run = (n, range);
+                        }
+                    }
+                }
+            }
+            OrthoEdge {
+                start: (xs[0], route.start.1),
+                end: (xs[xs.len() - 1], route.end.1),
+                xs,
+                rank: *scale.ranks.get(route.tail) as usize,
+            }
+        })
+        .collect();
+    let channels: Vec<_> = scale
+        .rank_centers
+        .iter()
+        .zip(&scale.rank_heights)
+        .zip(scale.rank_centers.iter().zip(&scale.rank_heights).skip(1))
+        .map(|((center, height), (next, next_height))| {
+            (center + height / 2., next - next_height / 2.)
+        })
+        .collect();
+    let mut paths = ortho::route(&edges, &channels).into_iter();
+    // Flat edges go straight between neighbor nodes or as arcs.
+    routes
+        .iter()
+        .map(|route| {
+            if is_flat(route) {
+                vec![route.start, route.end]
+            } else {
+                paths.next().unwrap()
+            }
+        })
+        .collect()
+}
+
+/// Curves of the straight segments of the polyline, control points are at
+/// thirds of the segments.
+fn straight(polyline: &[Point]) -> Vec<Point> {
+    let mut points = vec![polyline[0]];
+    for pair in polyline.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let third = ((b.0 - a.0) / 3., (b.1 - a.1) / 3.);
+        points.push((a.0 + third.0, a.1 + third.1));
+        points.push((b.0 - third.0, b.1 - third.1));
+        points.push(b);
+    }
+    points
+}
+
+/// Edges of the input edge split by virtual nodes.
+fn chain<T>(graph: &DirectedGraph<T>, edge_id: EdgeId) -> Vec<EdgeId> {
+    let mut segments = vec![edge_id];
+    let mut to = graph.edge(edge_id).to;
+    while graph.node(to).is_virtual {
+        let next = graph.node(to).outputs[0];
+        segments.push(next);
+        to = graph.edge(next).to;
+    }
+    segments
+}
+
+/// Whether the point is inside of the node outline, shapes are turned to the
+/// frame.
+fn contains(dot: &DotGraph, scale: &Scale, id: NodeId, point: Point) -> bool {
+    let rect = scale.node_box(id);
+    let (x, y) = rect.center();
+    let rankdir = scale.options.rankdir;
+    let size = if rankdir.is_horizontal() {
+        (rect.height, rect.width)
+    } else {
+        (rect.width, rect.height)
+    };
+    let point = rankdir.orient_point((point.0 - x, point.1 - y), (0., 0.));
+    Shape::from_attributes(dot.node_attributes.get(id)).contains(size, point)
+}
+
+/// Cuts the start of the curves inside of a node by bisection, curves which
+/// are completely inside are removed.
+fn clip(points: &mut Vec<Point>, inside: impl Fn(Point) -> bool) {
+    if !inside(points[0]) {
+        return;
+    }
+    let Some(first) = (0..points.len() - 1)
+        .step_by(3)
+        .find(|&n| !inside(points[n + 3]))
+    else {
+        return;
+    };
+    points.drain(..first);
+    let curve = [points[0], points[1], points[2], points[3]];
+    let (mut low, mut high) = (0., 1.);
+    for _ in 0..50 {
+        let middle = (low + high) / 2.;
+        if inside(spline::bezier(&curve, middle)) {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    points.splice(..4, split(&curve, high));
+}
+
+/// Part of the cubic Bezier curve after `t` by de Casteljau's algorithm.
+fn split(points: &[Point; 4], t: f64) -> [Point; 4] {
+    let lerp = |a: Point, b: Point| (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+    let [p0, p1, p2, p3] = *points;
+    let (p01, p12, p23) = (lerp(p0, p1), lerp(p1, p2), lerp(p2, p3));
+    let (p012, p123) = (lerp(p01, p12), lerp(p12, p23));
+    [lerp(p012, p123), p123, p23, p3]
+}
+
+/// Moves the end of the curves 1 point back along their direction, the last
+/// control point moves with it to keep the direction.
+fn shorten(points: &mut [Point]) {
+    let end = points[points.len() - 1];
+    let Some(&before) = points[..points.len() - 1].iter().rev().find(|p| **p != end) else {
+        return;
+    };
+    let (dx, dy) = (end.0 - before.0, end.1 - before.1);
+    let length = dx.hypot(dy);
+    let count = points.len();
+    for point in &mut points[count - 2..] {
+        *point = (point.0 - dx / length, point.1 - dy / length);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_dot::parse;
+
+    fn assert_near(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+    }
+
+    /// Distance from the ellipse inscribed in the rect in its radii.
+    fn ellipse_distance(rect: &Rect, (x, y): Point) -> f64 {
+        let (cx, cy) = rect.center();
+        ((x - cx) / rect.width * 2.).hypot((y - cy) / rect.height * 2.)
+    }
+
+    #[test]
+    fn edges_through_virtual_nodes() {
+        let dot = parse("digraph { a -> b; b -> c; a -> c; c -> a }").unwrap();
+        let layout = crate::layout(&dot, &LayoutOptions::default());
+        let (a, c) = (NodeId::from(0u32), NodeId::from(2u32));
+        // Nodes are 40x36, the size of Times 14 `a` with margins is 32x36.
+        assert_eq!(layout.ranks, &[18., 104., 190.]);
+        assert_eq!(layout.nodes.get(a).center().1, 18.);
+        assert_eq!(layout.nodes.get(c).center().1, 190.);
+
+        let a_c = layout.edges.get(EdgeId::from(2u32));
+        // The straight edge through the virtual node is a single curve.
+        assert_eq!(a_c.points.len(), 4);
+        assert_eq!(a_c.polyline().count(), 2);
+        assert_near(a_c.points[0].1, layout.nodes.get(a).bottom());
+        assert_near(a_c.points[3].1, layout.nodes.get(c).y - 1.);
+        assert_near(a_c.arrow.unwrap().1, layout.nodes.get(c).y);
+
+        // The inverted edge goes up from its tail to the arrow at the head.
+        let c_a = layout.edges.get(EdgeId::from(3u32));
+        assert_near(ellipse_distance(layout.nodes.get(c), c_a.points[0]), 1.);
+        assert_near(
+            ellipse_distance(layout.nodes.get(a), c_a.arrow.unwrap()),
+            1.,
+        );
+        assert!(layout.bounding_box.height >= layout.nodes.get(c).bottom());
+        // It goes around the other edge through its virtual node.
+        assert!(c_a.polyline().any(|p| p.0 > layout.nodes.get(a).right()));
+        assert!(c_a.back);
+        assert!(!a_c.back);
+    }
+
+    #[test]
+    fn back_edges() {
+        let dot = parse("digraph { a -> b -> c -> d; d -> a }").unwrap();
+        let layout = crate::layout(&dot, &LayoutOptions::default());
+        let backs: Vec<_> = layout.edges.iter().map(|(_, e)| e.back).collect();
+        assert_eq!(backs, &[false, false, false, true]);
+        // The long back edge keeps its direction through the virtual nodes.
+        let d_a = layout.edges.get(EdgeId::from(3u32));
+        let (a, d) = (
+            layout.nodes.get(NodeId::from(0u32)),
+            layout.nodes.get(NodeId::from(3u32)),
+        );
+        assert_near(ellipse_distance(d, d_a.points[0]), 1.);
+        assert_near(ellipse_distance(a, d_a.arrow.unwrap()), 1.);
+
+        let mut options = LayoutOptions::default();
+        options.set("backedges", "highlight");
+        assert_eq!(options.back_edges, BackEdges::Highlight);
+        options.set("backedges", "x");
+        assert_eq!(options.back_edges, BackEdges::Highlight);
+    }
+
+    #[test]
+    fn options() {
+        let dot = parse(
+            "digraph { nodesep=1; ranksep=\"0.5 equally\"; margin=\"0.5,0\"; a -> b; a -> c }",
+        )
+        .unwrap();
+        let options = LayoutOptions::from_attributes(&dot.attributes);
+        assert_eq!(options.nodesep, 72.);
+        assert_eq!(options.ranksep, 36.);
+        assert_eq!(options.margin, (36., 0.));
+
+        let layout = crate::layout(&dot, &options);
+        let (b, c) = (NodeId::from(1u32), NodeId::from(2u32));
+        let (b, c) = (layout.nodes.get(b), layout.nodes.get(c));
+        assert_eq!((b.x - c.x).abs(), 40. + 72.);
+        assert_eq!(b.y, 36. + 36.);
+        assert_eq!(b.x.min(c.x), 36.);
+        assert_eq!(layout.bounding_box.width, 36. * 2. + 40. * 2. + 72.);
+
+        let mut options = LayoutOptions::default();
+        options.set("mclimit", "0.5");
+        options.set("start", "random42");
+        options.set("nodesep", "-1");
+        assert_eq!((options.iterations, options.seed), (12, Some(42)));
+        assert_eq!(options.nodesep, LayoutOptions::default().nodesep);
+        for huge in ["1e20", "inf", "NaN"] {
+            options.set("mclimit", huge);
+            assert_eq!(options.iterations, 12);
+        }
+        for huge in ["1e8", "1e300", "101", "inf"] {
+            for name in ["nodesep", "ranksep", "margin"] {
+                options.set(name, huge);
+            }
+            assert_eq!(options.nodesep, LayoutOptions::default().nodesep);
+            assert_eq!(options.ranksep, LayoutOptions::default().ranksep);
+            assert_eq!(options.margin, (0., 0.));
+        }
+        options.set("mclimit", "100");
+        assert_eq!(options.iterations, 2400);
+
+        // The largest values still fit the coordinates.
+        let input = "digraph { nodesep=1e300; ranksep=100; margin=100; a -> b; a -> c; \
+                     b [width=1e300]; c [width=100, height=100] }";
+        let dot = parse(input).unwrap();
+        let mut options = LayoutOptions::from_attributes(&dot.attributes);
+        assert_eq!(options.ranksep, MAX_INCHES * 72.);
+        assert_eq!(options.nodesep, LayoutOptions::default().nodesep);
+        options.set("nodesep", "100");
+        let layout = crate::layout(&dot, &options);
+        assert!(layout.bounding_box.width > 4. * MAX_INCHES * 72.);
+    }
+
+    #[test]
+    fn rankdir() {
+        let input = "digraph { rankdir=LR; a -> b; a -> c }";
+        let dot = parse(input).unwrap();
+        let options = LayoutOptions::from_attributes(&dot.attributes);
+        assert_eq!(options.rankdir, RankDir::LeftToRight);
+        let layout = crate::layout(&dot, &options);
+        let [a, b, c] = [0u32, 1, 2].map(|n| *layout.nodes.get(NodeId::from(n)));
+        assert_eq!((a.width, a.height), (40., 36.));
+        assert_eq!((a.x, b.x), (0., 90.));
+        assert_eq!(b.x, c.x);
+        assert_eq!((b.y - c.y).abs(), 36. + 10.);
+        assert_eq!(layout.ranks, &[20., 110.]);
+        // Edges go from the right side of the tail to the left side of the head.
+        let edge = layout.edges.get(EdgeId::from(0u32));
+        assert!(edge.points[0].0 > a.center().0);
+        assert_near(ellipse_distance(&a, edge.points[0]), 1.);
+        assert!(edge.arrow.unwrap().0 < b.center().0);
+        assert_near(ellipse_distance(&b, edge.arrow.unwrap()), 1.);
+        assert_eq!(layout.bounding_box.width, 130.);
+
+        let dot = parse("digraph { rankdir=BT; a -> b }").unwrap();
+        let layout = crate::layout(&dot, &LayoutOptions::from_attributes(&dot.attributes));
+        let [a, b] = [0u32, 1].map(|n| *layout.nodes.get(NodeId::from(n)));
+        assert_eq!((a.y, b.y), (86., 0.));
+        assert_eq!(layout.ranks, &[104., 18.]);
+        let edge = layout.edges.get(EdgeId::from(0u32));
+        assert_near(edge.points[0].1, a.y);
+        assert_near(edge.arrow.unwrap().1, b.bottom());
+
+        let dot = parse("digraph { rankdir=RL; a -> b }").unwrap();
+        let layout = crate::layout(&dot, &LayoutOptions::from_attributes(&dot.attributes));
+        let [a, b] = [0u32, 1].map(|n| *layout.nodes.get(NodeId::from(n)));
+        assert_eq!((a.x, b.x), (90., 0.));
+    }
+
+    #[test]
+    fn clip_by_shapes() {
+        let dot = parse("digraph { a [shape=box]; b [shape=diamond]; a -> b; a -> c }").unwrap();
+        let layout = crate::layout(&dot, &LayoutOptions::default());
+        let [a, b] = [0u32, 1].map(|n| *layout.nodes.get(NodeId::from(n)));
+        // Edges leave the box through its bottom side.
+        for edge in [0u32, 1].map(|n| layout.edges.get(EdgeId::from(n))) {
+            assert_near(edge.points[0].1, a.bottom());
+        }
+        // The arrow touches the diamond side.
+        let (x, y) = layout.edges.get(EdgeId::from(0u32)).arrow.unwrap();
+        let (cx, cy) = b.center();
+        assert_near(
+            (x - cx).abs() / b.width * 2. + (y - cy).abs() / b.height * 2.,
+            1.,
+        );
+    }
+
+    #[test]
+    fn splines() {
+        let input = "digraph { a -> b; b -> c; a -> c; c -> a; a -> d }";
+        let dot = parse(input).unwrap();
+        let mut options = LayoutOptions::default();
+        options.set("splines", "ortho");
+        assert_eq!(options.splines, Splines::Ortho);
+        let layout = crate::layout(&dot, &options);
+        for (_, edge) in layout.edges.iter() {
+            let points: Vec<_> = edge.polyline().collect();
+            for pair in points.windows(2) {
+                let (dx, dy) = (pair[1].0 - pair[0].0, pair[1].1 - pair[0].1);
+                assert!(dx.abs() < 1e-6 || dy.abs() < 1e-6, "{:?}", pair);
+            }
+        }
+
+        options.set("splines", "false");
+        assert_eq!(options.splines, Splines::Line);
+        let layout = crate::layout(&dot, &options);
+        // Edges are straight even through virtual nodes.
+        for (_, edge) in layout.edges.iter() {
+            assert_eq!(edge.points.len(), 4);
+        }
+
+        options.set("splines", "polyline");
+        let layout = crate::layout(&dot, &options);
+        let c_a = layout.edges.get(EdgeId::from(3u32));
+        let a = layout.nodes.get(NodeId::from(0u32));
+        assert!(c_a.polyline().any(|p| p.0 > a.right()));
+    }
+
+    #[test]
+    fn edge_labels() {
+        let input = "digraph { a -> b [label=\"long label\", taillabel=t]; a -> c; b -> c }";
+        let dot = parse(input).unwrap();
+        let layout = crate::layout(&dot, &LayoutOptions::default());
+        let [a, b, c] = [0u32, 1, 2].map(|n| *layout.nodes.get(NodeId::from(n)));
+        // Ranks are doubled, the label is on its own rank and the distance
+        // between node ranks stays the same.
+        assert_eq!(layout.ranks.len(), 5);
+        assert_eq!(b.y - a.bottom(), 50. + 16.8);
+        let a_b = layout.edges.get(EdgeId::from(0u32));
+        let (x, y) = a_b.label.unwrap();
+        assert_eq!(y, layout.ranks[1]);
+        // The label is to the right of the edge and does not cover nodes.
+        let attributes = dot.edge_attributes.get(EdgeId::from(0u32));
+        let (width, height) = label::text_size(attributes, "label").unwrap();
+        let (left, right) = (x - width / 2., x + width / 2.);
+        let (top, bottom) = (y - height / 2., y + height / 2.);
+        assert!(a_b
+            .polyline()
+            .all(|p| p.1 < top || p.1 > bottom || p.0 < left));
+        for node in [a, b, c] {
+            assert!(
+                left > node.right() || right < node.x || bottom < node.y || top > node.bottom()
+            );
+        }
+        // The tail label is next to the tail.
+        let (x, y) = a_b.tail_label.unwrap();
+        let (dx, dy) = (x - a_b.points[0].0, y - a_b.points[0].1);
+        assert!(dx.hypot(dy) < 20.);
+        assert_eq!(layout.edges.get(EdgeId::from(1u32)).label, None);
+    }
+
+    #[test]
+    fn self_loops() {
+        let input = "digraph { a -> a; b -> a; b -> c; a -> a [label=x] }";
+        let dot = parse(input).unwrap();
+        let layout = crate::layout(&dot, &LayoutOptions::default());
+        let a = *layout.nodes.get(NodeId::from(0u32));
+        assert_eq!(layout.self_edges.len(), 2);
+        // Loops start and end at the node outline on its right side.
+        for edge in &layout.self_edges {
+            let start = edge.points[0];
+            assert_near(ellipse_distance(&a, start), 1.);
+            assert_near(ellipse_distance(&a, edge.arrow.unwrap()), 1.);
+            assert!(start.0 > a.center().0 && start.1 < a.center().1);
+            assert!(edge.arrow.unwrap().1 > a.center().1);
+        }
+        // The second loop goes around the first one, its label is on the right
+        // and the node on the same rank is further.
+        let right = |edge: &EdgeLayout| edge.points.iter().map(|p| p.0).fold(0., f64::max);
+        let (first, second) = (&layout.self_edges[0], &layout.self_edges[1]);
+        assert_eq!(right(first), a.right() + self_loop::LOOP_STEP);
+        assert!(right(second) > right(first));
+        let (x, _) = second.label.unwrap();
+        assert!(x > right(second));
+        let c = layout.nodes.get(NodeId::from(2u32));
+        assert_eq!(c.y, a.y);
+        assert!(c.x > x || c.right() < a.x);
+        assert!(layout.bounding_box.right() >= right(second));
+    }
+
+    #[test]
+    fn flat_edges() {
+        let input = "digraph { {rank=same; b; a; c} a -> c [label=x]; a -> b; d -> a; d -> c }";
+        let dot = parse(input).unwrap();
+        let layout = crate::layout(&dot, &LayoutOptions::default());
+        let node = |n: u32| *layout.nodes.get(NodeId::from(n));
+        let (b, a, c) = (node(0), node(1), node(2));
+        assert!(a.right() < b.x && b.right() < c.x);
+        // Neighbors are linked by a straight edge.
+        let straight = layout.edges.get(EdgeId::from(1u32));
+        for point in &straight.points {
+            assert_near(point.1, a.center().1);
+        }
+        // The labeled edge goes above the rank with the label on top, both
+        // fit above the first rank.
+        let arc = layout.edges.get(EdgeId::from(0u32));
+        let top = arc.points.iter().map(|p| p.1).fold(f64::MAX, f64::min);
+        assert!(top < a.y);
+        let (_, label_y) = arc.label.unwrap();
+        assert!(label_y < top && label_y > 0.);
+        assert!(arc.arrow.unwrap().0 > c.x && arc.arrow.unwrap().1 < c.center().1);
+
+        let options = LayoutOptions {
+            splines: Splines::Ortho,
+            ..LayoutOptions::default()
+        };
+        let layout = crate::layout(&dot, &options);
+        let arc = layout.edges.get(EdgeId::from(0u32));
+        assert!(arc.polyline().any(|p| p.1 < a.y));
+    }
+
+    #[test]
+    fn multi_edges() {
+        let dot = parse("digraph { a -> b; a -> b; a -> b; b -> c }").unwrap();
+        let mut options = LayoutOptions::default();
+        let all = [
+            Splines::Spline,
+            Splines::Polyline,
+            Splines::Line,
+            Splines::Ortho,
+        ];
+        for splines in all {
+            options.splines = splines;
+            let layout = crate::layout(&dot, &options);
+            // Parallel edges go to different sides, the middle one of them is
+            // straight.
+            let a = layout.nodes.get(NodeId::from(0u32)).center().0;
+            let side = |id: u32| {
+                let points = &layout.edges.get(EdgeId::from(id)).points;
+                points.iter().map(|p| p.0 - a).sum::<f64>()
+            };
+            let sides = [side(0), side(1), side(2)];
+            assert!(sides[0] > 0. && sides[2] < 0., "{:?} {:?}", splines, sides);
+            assert_near(sides[1], 0.);
+        }
+
+        let input = "digraph { a -> b; a -> b; a -> b [label=x]; a -> b; b -> c }";
+        let dot = parse(input).unwrap();
+        let mut options = LayoutOptions::from_attributes(
+            &parse("digraph { concentrate=true }").unwrap().attributes,
+        );
+        assert_eq!(options.multi_edges, MultiEdges::Merge);
+        let layout = crate::layout(&dot, &options);
+        let counts: Vec<_> = layout.edges.iter().map(|(_, e)| e.count).collect();
+        assert_eq!(counts, &[4, 0, 0, 0, 1]);
+        let (first, second) = (
+            layout.edges.get(EdgeId::from(0u32)),
+            layout.edges.get(EdgeId::from(1u32)),
+        );
+        assert_eq!(first.points, second.points);
+        assert!(first.label.is_some() && second.label.is_none());
+
+        options.set("concentrate", "false");
+        assert_eq!(options.multi_edges, MultiEdges::Fan);
+    }
+
+    #[test]
+    fn bounding_box() {
+        let input = "digraph { subgraph cluster_q { label=Q; a -> b [taillabel=tail]; \
+                     subgraph cluster_r { c; c -> c [label=loop] } } \
+                     b -> d [headlabel=\"a long head label\"]; d -> a [label=back] }";
+        let inside = |b: &Rect, rect: &Rect| {
+            rect.x >= b.x
+                && rect.y >= b.y
+                && rect.right() <= b.right() + 1e-9
+                && rect.bottom() <= b.bottom() + 1e-9
+        };
+        let point = |&(x, y): &Point| Rect {
+            x,
+            y,
+            width: 0.,
+            height: 0.,
+        };
+        for rankdir in ["TB", "LR", "BT", "RL"] {
+            let mut options = LayoutOptions::default();
+            options.set("rankdir", rankdir);
+            let layout = crate::layout(&parse(input).unwrap(), &options);
+            let b = layout.bounding_box;
+            assert_eq!((b.x, b.y), (0., 0.));
+            for cluster in layout.clusters.iter().flatten() {
+                assert!(inside(&b, cluster), "{} {:?} {:?}", rankdir, cluster, b);
+            }
+            for (_, node) in layout.nodes.iter() {
+                assert!(inside(&b, node), "{} {:?} {:?}", rankdir, node, b);
+            }
+            let edges = layout.edges.iter().map(|(_, edge)| edge);
+            for edge in edges.chain(&layout.self_edges) {
+                let labels = [edge.label, edge.head_label, edge.tail_label];
+                for p in edge.points.iter().chain(labels.iter().flatten()) {
+                    assert!(inside(&b, &point(p)), "{} {:?} {:?}", rankdir, p, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cluster_padding() {
+        let input = "digraph { subgraph cluster_a { a -> b; subgraph cluster_b { c } } }";
+        let layout = crate::layout(&parse(input).unwrap(), &LayoutOptions::default());
+        let outer = layout.clusters[0].unwrap();
+        let inner = layout.clusters[1].unwrap();
+        assert_eq!((outer.x, outer.y), (0., 0.));
+        let b = layout.bounding_box;
+        assert_eq!((outer.right(), outer.bottom()), (b.width, b.height));
+        // The nested cluster takes a padding inside the outer one.
+        assert_eq!(inner.y - outer.y, 6.);
+        let nodes: Vec<_> = layout.nodes.iter().map(|(_, n)| *n).collect();
+        let left = nodes.iter().map(|n| n.x).fold(f64::MAX, f64::min);
+        let top = nodes.iter().map(|n| n.y).fold(f64::MAX, f64::min);
+        assert_eq!((left, top), (12., 12.));
+
+        // The label takes the top of the cluster and the rank gap above it.
+        let input = "digraph { x -> a; subgraph cluster_a { label=Title; a -> b } }";
+        let dot = parse(input).unwrap();
+        let options = LayoutOptions {
+            ranksep: 10.,
+            ..LayoutOptions::default()
+        };
+        let layout = crate::layout(&dot, &options);
+        let cluster = layout.clusters[0].unwrap();
+        let [x, a] = [0u32, 1].map(|n| *layout.nodes.get(NodeId::from(n)));
+        let label_height = 14. * 1.2;
+        assert_near(a.y - cluster.y, 6. + label_height);
+        assert!(cluster.y >= x.bottom() + 10., "{:?} {:?}", cluster, x);
+    }
+}
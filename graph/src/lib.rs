@@ -1,33 +1,112 @@
 #[macro_use]
 extern crate log;
+use layout::{Layout, LayoutOptions};
 use read_dot::DotGraph;
 
 use self::graph::*;
 use std::fmt::Debug;
 
 pub mod add_virtual_nodes;
+pub mod cluster;
 pub mod draw;
+pub mod flat_edge;
 pub mod generator;
 pub mod graph;
+pub mod label;
+pub mod layout;
+pub mod multi_edge;
 pub mod ns;
+pub mod ortho;
+pub mod parse_error;
 pub mod place;
+pub mod port;
+pub mod rank_constraints;
 pub mod read_dot;
+pub mod record;
+pub mod self_loop;
+pub mod shape;
+pub mod spline;
+pub mod text;
 pub mod to_dag;
+pub mod write_dot;
+pub mod write_layout;
 pub mod xcoord;
 extern crate pest;
 extern crate pest_derive;
 
-pub fn full_draw<'a>(mut dot: DotGraph<'a>, extra_edges: Option<&NodeMap<(u32, u32)>>) -> Vec<u8> {
-    to_dag::to_dag(&mut dot.graph);
-    let mut ranks = rank_with_components(&dot.graph);
-    add_virtual_nodes::add_virtual_nodes(&mut dot.graph, &mut ranks);
-    let places = place::places3(&dot.graph, &ranks);
-    let coords = xcoord::x_coordinates(&dot.graph, &ranks, &places);
+pub fn full_draw<'a>(
+    dot: DotGraph<'a>,
+    extra_edges: Option<&NodeMap<(u32, u32)>>,
+    options: &LayoutOptions,
+) -> Vec<u8> {
+    let mut output = vec![];
+    let layout = layout(&dot, options);
+    draw::draw(&dot, &layout, extra_edges, options, &mut output);
+    output
+}
+
+/// Text layout formats, SVG is written by [`full_draw`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Dot,
+    Plain,
+    PlainExt,
+    Json,
+}
+
+/// Lays out the graph and writes it in one of the Graphviz text formats.
+pub fn full_write(dot: DotGraph, format: OutputFormat, options: &LayoutOptions) -> Vec<u8> {
     let mut output = vec![];
-    draw::draw(&dot, &ranks, &coords, extra_edges, &mut output);
+    let layout = layout(&dot, options);
+    match format {
+        OutputFormat::Dot => write_dot::write_dot(&dot, Some(&layout), &mut output),
+        OutputFormat::Plain => write_layout::write_plain(&dot, &layout, false, &mut output),
+        OutputFormat::PlainExt => write_layout::write_plain(&dot, &layout, true, &mut output),
+        OutputFormat::Json => write_layout::write_json(&dot, &layout, &mut output),
+    }
+    .expect("write to vec");
     output
 }
 
+/// Runs all layout phases on a copy of the graph, the layout is made top to
+/// bottom and turned to `rankdir` at the end. Edge labels double ranks,
+/// merged parallel edges are laid out as one.
+pub fn layout(dot: &DotGraph, options: &LayoutOptions) -> Layout {
+    if options.multi_edges == layout::MultiEdges::Merge {
+        if let Some((merged, edges)) = multi_edge::merge(dot) {
+            return layout(&merged, options).unmerge(&edges);
+        }
+    }
+    let rankdir = options.rankdir;
+    let mut dot = dot.clone();
+    to_dag::to_dag(&mut dot.graph);
+    let mut labels = label::EdgeLabels::new(&dot, rankdir);
+    labels.double_ranks(&mut dot.graph);
+    let constraints = rank_constraints::rank_constraints(&dot.subgraphs);
+    let mut ranks = rank_constraints::rank(&mut dot.graph, &constraints);
+    let edges_count = dot.graph.edges_count();
+    let mut clusters = cluster::Clusters::new(&dot);
+    let mut ports = port::Ports::new(&dot, rankdir);
+    add_virtual_nodes::add_virtual_nodes(&mut dot.graph, &mut ranks);
+    clusters.add_virtual_nodes(&dot.graph, edges_count);
+    ports.add_virtual_nodes(&dot.graph, edges_count);
+    labels.add_virtual_nodes(&dot.graph);
+    let mut sizes = layout::node_sizes(&dot, options);
+    labels.set_sizes(&mut sizes);
+    let mut options = options.frame();
+    // Doubled ranks keep the distance between ranks of nodes.
+    if !labels.is_empty() {
+        options.ranksep /= 2.;
+    }
+    let options = &options;
+    let places = place::places3(&dot.graph, &ranks, &clusters, &ports, options);
+    let loops = self_loop::loop_widths(&dot, rankdir);
+    let coords = xcoord::x_coordinates(
+        &dot.graph, &ranks, &places, &clusters, &sizes, &loops, options,
+    );
+    Layout::new(&dot, &ranks, &coords, &sizes, &clusters, &ports, options).orient(rankdir)
+}
+
 pub fn subgraph<'a>(
     dot: &'a DotGraph,
     opt_start: Option<NodeId>,
@@ -35,13 +114,14 @@ pub fn subgraph<'a>(
     max_edges: u32,
 ) -> (DotGraph<'a>, NodeMap<(u32, u32)>) {
     let start = opt_start
-        .or_else(|| dot.graph.roots().get(0).copied())
+        .or_else(|| dot.graph.roots().first().copied())
         .or_else(|| dot.graph.iter_nodes_ids().next())
         .expect("need start");
     debug!("subgraph: start from {start:?}");
     //TODO: remember used root
     let mut output = DirectedGraph::default();
     let mut map = dot.graph.node_map::<Option<NodeId>>();
+    let mut edge_map = dot.graph.edge_map::<Option<EdgeId>>();
     let mut queue = vec![start];
     let mut next_queue = vec![];
     while !queue.is_empty() && output.nodes_count() < max_nodes && output.edges_count() < max_edges
@@ -51,7 +131,7 @@ pub fn subgraph<'a>(
             let new_node_id = output.add_node(Node::default());
             debug!("subgraph: add {node_id:?} as {new_node_id:?}");
             map.set(node_id, Some(new_node_id));
-            for (_, _, edge, direction) in dot.graph.iter_node_edges(node_id) {
+            for (_, old_edge_id, edge, direction) in dot.graph.iter_node_edges(node_id) {
                 let (other_id, is_output) = if direction == Direction::Output {
                     (edge.to, true)
                 } else {
@@ -59,12 +139,20 @@ pub fn subgraph<'a>(
                 };
                 if let Some(new_other_id) = map.get(other_id).to_owned() {
                     debug!("subgraph: copy edge {edge:?}");
-                    // TODO: copy edge params
                     let edge_id = output.add_edge(if is_output {
-                        Edge::new(new_node_id, new_other_id)
+                        Edge {
+                            from: new_node_id,
+                            to: new_other_id,
+                            ..*edge
+                        }
                     } else {
-                        Edge::new(new_other_id, new_node_id)
+                        Edge {
+                            from: new_other_id,
+                            to: new_node_id,
+                            ..*edge
+                        }
                     });
+                    edge_map.set(old_edge_id, Some(edge_id));
                     if is_output {
                         output.node_mut(new_node_id).outputs.push(edge_id);
                         output.node_mut(new_other_id).inputs.push(edge_id);
@@ -107,8 +195,8 @@ pub fn subgraph<'a>(
         }
     }
 
-    // map labels
-    (dot.map_to_new(output, map), extra_edges)
+    // map attributes
+    (dot.map_to_new(output, map, edge_map), extra_edges)
 }
 
 pub fn rank_with_components<T: Debug>(graph: &DirectedGraph<T>) -> NodeMap<i32> {
@@ -335,7 +423,7 @@ mod tests {
 
     #[test]
     fn rank_loop_and_dot() {
-        let _ = init_log();
+        init_log();
         let graph = DirectedGraph::new(&[0], &[(1, 2), (2, 3), (1, 3)]);
         let ranks = rank_with_components(&graph);
         assert_eq!(
@@ -346,7 +434,7 @@ mod tests {
 
     #[test]
     fn test_subgraph() {
-        let _ = init_log();
+        init_log();
         let dot = read_dot::parse("digraph x {a->b; a->c; a->d; a->e; b->f; b->c;}").unwrap();
         let (new, _) = subgraph(&dot, None, 3, 10);
         assert_eq!(new.graph.nodes_count(), 3);
@@ -354,6 +442,23 @@ mod tests {
         //TODO: add more cases and checks.
     }
 
+    #[test]
+    fn subgraph_self_edges() {
+        let input = "digraph x {a->b; b->c; a->a [label=x]; c->c; b->b}";
+        let dot = read_dot::parse(input).unwrap();
+        let (new, _) = subgraph(&dot, None, 2, 10);
+        assert_eq!(new.graph.nodes_count(), 2);
+        let loops: Vec<_> = new
+            .graph
+            .iter_self_edges()
+            .map(|&id| new.graph.original_id(id).unwrap().as_ref())
+            .collect();
+        assert_eq!(loops, &["a", "b"]);
+        assert_eq!(new.self_edge_attributes.len(), 2);
+        assert_eq!(new.self_edge_attributes[0].get("label").unwrap(), "x");
+        layout(&new, &LayoutOptions::default());
+    }
+
     pub fn init_log() {
         _ = simplelog::TermLogger::init(
             simplelog::LevelFilter::Debug,
@@ -0,0 +1,202 @@
+//! Parallel edges, several edges between the same pair of nodes. By default
+//! edges going to the next rank are fanned out, curves with different
+//! curvatures and straight ones side by side, longer ones already get
+//! separate virtual nodes. With
+//! `concentrate=true` they are merged into one edge with the number of edges
+//! in its label.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::graph::*;
+use crate::layout::Point;
+use crate::read_dot::{Attributes, DotGraph};
+
+/// Distance between the middles of neighbor fanned edges.
+pub const FAN_STEP: f64 = 12.;
+
+/// Edge of the merged graph drawn for an input edge.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MergedEdge {
+    pub edge: EdgeId,
+    /// Number of input edges merged into `edge` for the first of them, 0 for
+    /// the others.
+    pub count: u32,
+}
+
+impl Default for MergedEdge {
+    fn default() -> Self {
+        MergedEdge {
+            edge: UNEXISTED_EDGE_ID,
+            count: 0,
+        }
+    }
+}
+
+/// Copy of the graph where parallel edges are merged into the first one of
+/// every group, undirected edges are parallel in both directions. Merged
+/// edges sum weights and count edges in the label. Returns it with the merged
+/// edge of every input edge, None if there are no parallel edges.
+pub fn merge<'a>(dot: &DotGraph<'a>) -> Option<(DotGraph<'a>, EdgeMap<MergedEdge>)> {
+    let graph = &dot.graph;
+    let mut firsts = HashMap::new();
+    let mut groups = graph.edge_map::<MergedEdge>();
+    for (id, edge) in graph.iter_edges_with_id() {
+        // Edges inverted by `to_dag` are grouped in the input direction.
+        let (from, to) = if edge.is_inverted() {
+            (edge.to, edge.from)
+        } else {
+            (edge.from, edge.to)
+        };
+        let key = if dot.directed || from <= to {
+            (from, to)
+        } else {
+            (to, from)
+        };
+        let first = *firsts.entry(key).or_insert(id);
+        groups.get_mut(id).edge = first;
+        groups.get_mut(first).count += 1;
+    }
+    if firsts.len() == graph.edges_count() as usize {
+        return None;
+    }
+
+    let mut merged = dot.clone();
+    let ids = merged.graph.retain_edges(|id, _| groups.get(id).edge == id);
+    let mut attributes = merged.graph.edge_map();
+    for (id, group) in groups.iter_mut() {
+        let first = group.edge;
+        group.edge = ids.get(first).expect("first edges are kept");
+        if id == first {
+            let mut first_attributes = dot.edge_attributes.get(id).clone();
+            if group.count > 1 {
+                let text = label(
+                    first_attributes.get("label").map(|l| l.as_ref()),
+                    group.count,
+                );
+                first_attributes.insert("label".into(), text.into());
+            }
+            attributes.set(group.edge, first_attributes);
+        } else {
+            merged.graph.edge_mut(group.edge).weight += graph.edge(id).weight;
+        }
+    }
+    merged.edge_attributes = attributes;
+    Some((merged, groups))
+}
+
+/// Label of an edge drawn for `count` parallel edges.
+pub fn label(label: Option<&str>, count: u32) -> String {
+    match label.filter(|l| !l.is_empty()) {
+        Some(label) => format!("{label} ×{count}"),
+        None => format!("×{count}"),
+    }
+}
+
+/// Text of the `label` of an edge drawn for `count` input edges.
+pub fn edge_label<'a>(attributes: &'a Attributes, count: u32) -> Option<Cow<'a, str>> {
+    let text = attributes.get("label").map(|l| l.as_ref());
+    if count > 1 {
+        Some(label(text, count).into())
+    } else {
+        text.map(Cow::Borrowed)
+    }
+}
+
+/// Middle of a fanned edge `offset` away from the straight line to the right
+/// of the direction from `start` to `end`.
+pub fn fan_middle(start: Point, end: Point, offset: f64) -> Point {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length = dx.hypot(dy);
+    let (nx, ny) = if length > 0. {
+        (-dy / length, dx / length)
+    } else {
+        (1., 0.)
+    };
+    (
+        (start.0 + end.0) / 2. + nx * offset,
+        (start.1 + end.1) / 2. + ny * offset,
+    )
+}
+
+/// Ends of a straight edge moved by `offset` to the same side as
+/// [`fan_middle`].
+pub fn parallel(start: Point, end: Point, offset: f64) -> [Point; 2] {
+    let middle = fan_middle(start, end, offset);
+    let (dx, dy) = (
+        middle.0 - (start.0 + end.0) / 2.,
+        middle.1 - (start.1 + end.1) / 2.,
+    );
+    [(start.0 + dx, start.1 + dy), (end.0 + dx, end.1 + dy)]
+}
+
+/// Control points of one cubic Bezier curve going through
+/// [`fan_middle`].
+pub fn fan(start: Point, end: Point, offset: f64) -> Vec<Point> {
+    let middle = fan_middle(start, end, offset);
+    let (mx, my) = ((start.0 + end.0) / 2., (start.1 + end.1) / 2.);
+    // The curve middle is 3/4 of the way from the chord to the control
+    // points.
+    let shift = ((middle.0 - mx) * 4. / 3., (middle.1 - my) * 4. / 3.);
+    let third = ((end.0 - start.0) / 3., (end.1 - start.1) / 3.);
+    vec![
+        start,
+        (start.0 + third.0 + shift.0, start.1 + third.1 + shift.1),
+        (end.0 - third.0 + shift.0, end.1 - third.1 + shift.1),
+        end,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_dot::parse;
+    use crate::spline;
+
+    #[test]
+    fn merged() {
+        let dot = parse("digraph { a -> b [weight=2]; a -> c; a -> b [label=x]; b -> a }").unwrap();
+        let (merged, map) = merge(&dot).unwrap();
+        assert_eq!(merged.graph.edges_count(), 3);
+        let (e0, e1) = (EdgeId::from(0u32), EdgeId::from(1u32));
+        assert_eq!(
+            map.iter()
+                .map(|(_, m)| (m.edge, m.count))
+                .collect::<Vec<_>>(),
+            &[(e0, 2), (e1, 1), (e0, 0), (EdgeId::from(2u32), 1)]
+        );
+        assert_eq!(merged.graph.edge(e0).weight, 3);
+        assert_eq!(merged.edge_attributes.get(e0).get("label").unwrap(), "×2");
+        let a = merged.graph.node(NodeId::from(0u32));
+        assert_eq!((a.outputs.len(), a.inputs.len()), (2, 1));
+
+        // Opposite undirected edges are parallel.
+        let dot = parse("graph { a -- b; b -- a [label=x]; a -- b }").unwrap();
+        let (merged, _) = merge(&dot).unwrap();
+        assert_eq!(merged.graph.edges_count(), 1);
+        assert!(merge(&parse("digraph { a -> b; b -> a }").unwrap()).is_none());
+
+        // Opposite edges stay apart after one of them is inverted.
+        let mut dot = parse("digraph { a -> b; b -> a; b -> a }").unwrap();
+        crate::to_dag::to_dag(&mut dot.graph);
+        let (merged, map) = merge(&dot).unwrap();
+        assert_eq!(merged.graph.edges_count(), 2);
+        let counts: Vec<_> = map.iter().map(|(_, m)| m.count).collect();
+        assert_eq!(counts, &[1, 2, 0]);
+        assert_eq!(label(Some("x"), 3), "x ×3");
+        let attributes = std::iter::once(("label".into(), "x".into())).collect();
+        assert_eq!(edge_label(&attributes, 1).unwrap(), "x");
+        assert_eq!(edge_label(&Attributes::new(), 2).unwrap(), "×2");
+    }
+
+    #[test]
+    fn fanned() {
+        let curve = fan((0., 0.), (0., 30.), 6.);
+        let middle = spline::bezier(&[curve[0], curve[1], curve[2], curve[3]], 0.5);
+        assert!((middle.0 - fan_middle((0., 0.), (0., 30.), 6.).0).abs() < 1e-9);
+        // Positive offsets go to the left of a downward edge, which is its
+        // right side.
+        assert!((middle.0 + 6.).abs() < 1e-9 && (middle.1 - 15.).abs() < 1e-9);
+        assert_eq!(parallel((0., 0.), (0., 30.), 6.), [(-6., 0.), (-6., 30.)]);
+    }
+}
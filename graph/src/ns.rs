@@ -13,40 +13,40 @@ pub fn network_simplex<T: Debug>(
     postprocess: Postprocess,
     opt_ranks: Option<NodeMap<i32>>,
 ) -> NodeMap<i32> {
-    let mut ranks = opt_ranks.unwrap_or(rank(&graph));
+    let mut ranks = opt_ranks.unwrap_or(rank(graph));
     debug!("initial ranks: {ranks:?}");
     graph.dump(
         &format!("tmp_ns_before_start_{postprocess:?}"),
-        &|_, id, _| format!("{:?} r:{}", id, ranks.get(id)).into(),
-        &|_, _, edge| format!("weight:{} min_len:{}", edge.weight, edge.min_length,).into(),
+        &|_, id, _| format!("{:?} r:{}", id, ranks.get(id)),
+        &|_, _, edge| format!("weight:{} min_len:{}", edge.weight, edge.min_length,),
         &|_, _| true,
         &|_, _| true,
     );
 
     _validate_rank(graph, &ranks);
-    let mut data = &mut span_tree(&graph, postprocess, &mut ranks);
-    _validate_rank(graph, &data.ranks);
-    _validate_length(graph, &data.ranks, &data.edges);
+    let data = &mut span_tree(graph, postprocess, &mut ranks);
+    _validate_rank(graph, data.ranks);
+    _validate_length(graph, data.ranks, &data.edges);
     minmax(data);
     cut_value(data);
 
     let mut iter = 0;
     let mut negative_edge_search = NegativeEdgeSearch::new();
-    while let Some(negative_edge) = negative_edge_search.next(&data) {
-        dump_graph(&data, iter, "replace_ready", Some(negative_edge));
+    while let Some(negative_edge) = negative_edge_search.next(data) {
+        dump_graph(data, iter, "replace_ready", Some(negative_edge));
         debug!(
             "search replacment for {:?} {:?}",
             negative_edge,
             data.graph.edge(negative_edge)
         );
-        let replacement_edge = find_replacement_edge(&mut data, negative_edge);
+        let replacement_edge = find_replacement_edge(data, negative_edge);
         debug!(
             "replace negative {:?} by {:?}",
             graph.edge(negative_edge),
             graph.edge(replacement_edge)
         );
-        replace_edge(&mut data, negative_edge, replacement_edge);
-        dump_graph(&data, iter, "replace_done", Some(replacement_edge));
+        replace_edge(data, negative_edge, replacement_edge);
+        dump_graph(data, iter, "replace_done", Some(replacement_edge));
         _validate_rank(graph, data.ranks);
         iter += 1;
     }
@@ -61,7 +61,7 @@ pub fn network_simplex<T: Debug>(
                 continue;
             }
 
-            let replacement_edge = find_replacement_edge(&mut data, edge_id);
+            let replacement_edge = find_replacement_edge(data, edge_id);
             let shift_ = (data
                 .edges
                 .get(replacement_edge)
@@ -75,7 +75,7 @@ pub fn network_simplex<T: Debug>(
                 graph.edge(replacement_edge)
             );
             if shift_ != 0 {
-                shift(&mut data, edge_id, shift_);
+                shift(data, edge_id, shift_);
             }
         }
     }
@@ -95,7 +95,7 @@ fn normalize(ranks: &mut NodeMap<i32>) {
 }
 
 fn rank<T: Debug>(graph: &DirectedGraph<T>) -> NodeMap<i32> {
-    let mut stack: Vec<_> = graph.roots().iter().copied().collect();
+    let mut stack: Vec<_> = graph.roots().to_vec();
     let mut found_inputs = graph.node_map::<u32>();
     let mut ranks = graph.node_map();
 
@@ -248,7 +248,7 @@ fn span_tree<'a, 'b, T: Debug>(
             &|_, id| edge_data.get(id).in_tree,
         );
 
-        _validate_length(graph, &ranks, &edge_data);
+        _validate_length(graph, ranks, &edge_data);
         // search for min edge
         let mut min_edge_id = None;
         let mut min_edge_slack = u32::MAX;
@@ -615,7 +615,9 @@ fn shift<'a, 'b, T: Debug>(
 #[cfg(debug_assertions)]
 fn _validate_rank<T: Debug>(graph: &DirectedGraph<T>, ranks: &NodeMap<i32>) {
     for (_, edge) in graph.iter_edges_with_id() {
-        if edge.to != edge.from && ranks.get(edge.to) - ranks.get(edge.from) < 1 {
+        if edge.to != edge.from
+            && ranks.get(edge.to) - ranks.get(edge.from) < edge.min_length as i32
+        {
             panic!(
                 "rank validate failed, {:?}:{:?}:{} to {:?}:{:?}:{}",
                 edge.from,
@@ -669,7 +671,7 @@ fn dump_graph<'a, 'b, T>(
 ) {
     data.graph.dump(
         &format!("tmp_ns_{:?}_{place}_{iter}", data.postprocess,),
-        &|_, id, _| format!("{:?} r:{}", id, data.ranks.get(id)).into(),
+        &|_, id, _| format!("{:?} r:{}", id, data.ranks.get(id)),
         &|_, id, edge| {
             format!(
                 "cut:{} slack:{} len:{} weight:{}{}",
@@ -679,7 +681,6 @@ fn dump_graph<'a, 'b, T>(
                 edge.weight,
                 if special_edge == Some(id) { " *" } else { "" },
             )
-            .into()
         },
         &|_, _| true,
         &|_, id| data.edges.get(id).in_tree,
@@ -738,7 +739,7 @@ mod tests {
         ranks.set(NodeId::from(3u32), 16);
         dag.edge_mut(EdgeId::from(1u32)).min_length = 2;
         dag.edge_mut(EdgeId::from(2u32)).min_length = 3;
-        let data = span_tree(&mut dag, Postprocess::None, &mut ranks);
+        let data = span_tree(&dag, Postprocess::None, &mut ranks);
         normalize(data.ranks);
         let tree_edges_count: usize = dbg!(&data.nodes)
             .iter()
@@ -765,13 +766,13 @@ mod tests {
               \   /
                 3
         */
-        let mut dag = DirectedGraph::new(&[0, 1, 2, 3], &[(0, 1), (0, 2), (1, 3), (2, 3)]);
+        let dag = DirectedGraph::new(&[0, 1, 2, 3], &[(0, 1), (0, 2), (1, 3), (2, 3)]);
         let mut ranks = dag.node_map();
         ranks.set(NodeId::from(0u32), 0);
         ranks.set(NodeId::from(1u32), 1);
         ranks.set(NodeId::from(2u32), 1);
         ranks.set(NodeId::from(3u32), 2);
-        let data = span_tree(&mut dag, Postprocess::None, &mut ranks);
+        let data = span_tree(&dag, Postprocess::None, &mut ranks);
         let tree_edges_count: usize = dbg!(&data.nodes)
             .iter()
             .map(|(_, n)| n.inputs.len() + n.outputs.len())
@@ -792,7 +793,7 @@ mod tests {
                  c--+----------a
 
         */
-        let mut dag = DirectedGraph::new(
+        let dag = DirectedGraph::new(
             &['2', '1', 'a', 'b', 'c', 'd'],
             &[
                 ('2', 'c'),
@@ -803,9 +804,9 @@ mod tests {
                 ('a', 'c'),
             ],
         );
-        let mut ranks = rank(&mut dag);
+        let mut ranks = rank(&dag);
         dbg!(&ranks.iter().collect::<Vec<_>>());
-        let mut data = span_tree(&mut dag, Postprocess::None, &mut ranks);
+        let mut data = span_tree(&dag, Postprocess::None, &mut ranks);
         let tree_edges_count: usize = dbg!(&data.nodes)
             .iter()
             .map(|(_, n)| n.inputs.len() + n.outputs.len())
@@ -826,12 +827,12 @@ mod tests {
     */
     #[test]
     fn minmax_test() {
-        let mut dag = DirectedGraph::new(
+        let dag = DirectedGraph::new(
             &['a', 'b', 'c', 'd', 'e'],
             &[('a', 'b'), ('b', 'c'), ('b', 'd'), ('a', 'e')],
         );
-        let mut ranks = rank(&mut dag);
-        let mut data = span_tree(&mut dag, Postprocess::None, &mut ranks);
+        let mut ranks = rank(&dag);
+        let mut data = span_tree(&dag, Postprocess::None, &mut ranks);
         minmax(&mut data);
         assert_eq!(data.nodes.get(NodeId::from(0u32)).min, 1);
         assert_eq!(data.nodes.get(NodeId::from(1u32)).min, 1);
@@ -862,8 +863,8 @@ mod tests {
         );
         dag.edge_mut(EdgeId::from(6u32)).weight = 0;
         dag.edge_mut(EdgeId::from(7u32)).weight = 0;
-        let mut ranks = rank(&mut dag);
-        let mut data = span_tree(&mut dag, Postprocess::None, &mut ranks);
+        let mut ranks = rank(&dag);
+        let mut data = span_tree(&dag, Postprocess::None, &mut ranks);
         minmax(&mut data);
         cut_value(&mut data);
         dbg!(&data);
@@ -878,7 +879,7 @@ mod tests {
         assert_eq!(data.edges.get(EdgeId::from(7u32)).cut_value, 1);
 
         let negatove_edge = dbg!(NegativeEdgeSearch::new().next(&data)).unwrap();
-        let replacement_edge = find_replacement_edge(&mut data, negatove_edge);
+        let replacement_edge = find_replacement_edge(&data, negatove_edge);
         dbg!(&negatove_edge, replacement_edge);
         replace_edge(&mut data, negatove_edge, replacement_edge);
         dbg!(&data, &data.ranks);
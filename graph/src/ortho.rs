@@ -0,0 +1,149 @@
+//! Edges of horizontal and vertical segments for `splines=ortho`. Edges go
+//! down vertically through ranks at the positions of their ends and virtual
+//! nodes, which are free from other nodes, and turn in the channels between
+//! ranks. Horizontal segments in a channel get their own tracks unless they
+//! start or end at the same point, then they share a track like a bus.
+
+use crate::layout::Point;
+
+/// Edge going down from `start` to `end` through the verticals at `xs`, from
+/// the tail to the head, the tail is on `rank`.
+#[derive(Clone, Debug)]
+pub struct OrthoEdge {
+    pub start: Point,
+    pub end: Point,
+    pub xs: Vec<f64>,
+    pub rank: usize,
+}
+
+/// Segment of an edge in a channel.
+struct Segment {
+    edge: usize,
+    channel: usize,
+    from: f64,
+    to: f64,
+}
+
+/// Polylines of the edges, `channels` are tops and bottoms of the space below
+/// every rank.
+pub fn route(edges: &[OrthoEdge], channels: &[(f64, f64)]) -> Vec<Vec<Point>> {
+    let mut segments = vec![];
+    for (index, edge) in edges.iter().enumerate() {
+        for (n, pair) in edge.xs.windows(2).enumerate() {
+            if (pair[0] - pair[1]).abs() > 0.5 {
+                segments.push(Segment {
+                    edge: index,
+                    channel: edge.rank + n,
+                    from: pair[0],
+                    to: pair[1],
+                });
+            }
+        }
+    }
+    let tracks = tracks(&segments, channels.len());
+
+    let mut paths: Vec<_> = edges.iter().map(|edge| vec![edge.start]).collect();
+    for (segment, (track, count)) in segments.iter().zip(tracks) {
+        let (top, bottom) = channels[segment.channel];
+        let y = top + (bottom - top) * (track + 1) as f64 / (count + 1) as f64;
+        let path = &mut paths[segment.edge];
+        path.push((segment.from, y));
+        path.push((segment.to, y));
+    }
+    for (path, edge) in paths.iter_mut().zip(edges) {
+        path.push(edge.end);
+        path.dedup();
+    }
+    paths
+}
+
+/// Track of every segment and the number of tracks in its channel.
+/// Segments with a common end are grouped and groups get tracks from the
+/// left to the right, the first free one.
+fn tracks(segments: &[Segment], channels: usize) -> Vec<(usize, usize)> {
+    // Groups by union find.
+    let mut parents: Vec<usize> = (0..segments.len()).collect();
+    fn root(parents: &mut [usize], n: usize) -> usize {
+        let mut n = n;
+        while parents[n] != n {
+            parents[n] = parents[parents[n]];
+            n = parents[n];
+        }
+        n
+    }
+    for a in 0..segments.len() {
+        for b in a + 1..segments.len() {
+            let (sa, sb) = (&segments[a], &segments[b]);
+            if sa.channel == sb.channel && (sa.from == sb.from || sa.to == sb.to) {
+                let (ra, rb) = (root(&mut parents, a), root(&mut parents, b));
+                parents[ra] = rb;
+            }
+        }
+    }
+    let mut groups = std::collections::BTreeMap::<usize, (usize, f64, f64)>::new();
+    for (n, segment) in segments.iter().enumerate() {
+        let (left, right) = (segment.from.min(segment.to), segment.from.max(segment.to));
+        let group = groups
+            .entry(root(&mut parents, n))
+            .or_insert((segment.channel, left, right));
+        (group.1, group.2) = (group.1.min(left), group.2.max(right));
+    }
+    let mut ordered: Vec<_> = groups.iter().collect();
+    ordered.sort_by(|a, b| (a.1 .0, a.1 .1).partial_cmp(&(b.1 .0, b.1 .1)).unwrap());
+    // Right ends of the last groups on every track of every channel.
+    let mut ends = vec![Vec::<f64>::new(); channels];
+    let mut group_tracks = std::collections::BTreeMap::new();
+    for (&group, &(channel, left, right)) in ordered {
+        let ends = &mut ends[channel];
+        let track = match ends.iter().position(|&end| end < left) {
+            Some(track) => track,
+            None => {
+                ends.push(0.);
+                ends.len() - 1
+            }
+        };
+        ends[track] = right;
+        group_tracks.insert(group, track);
+    }
+    (0..segments.len())
+        .map(|n| {
+            let track = group_tracks[&root(&mut parents, n)];
+            (track, ends[segments[n].channel].len())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels() {
+        let edge = |start: Point, end: Point, xs: &[f64]| OrthoEdge {
+            start,
+            end,
+            xs: xs.to_vec(),
+            rank: 0,
+        };
+        let edges = [
+            // Two edges from the same node share the track.
+            edge((50., 0.), (0., 100.), &[50., 0.]),
+            edge((50., 0.), (100., 100.), &[50., 100.]),
+            // A straight edge has no horizontal segment.
+            edge((200., 0.), (200., 100.), &[200., 200.]),
+            // An edge crossing the others gets its own track.
+            edge((150., 0.), (20., 100.), &[150., 20.]),
+        ];
+        let paths = route(&edges, &[(30., 60.)]);
+        assert_eq!(paths[0], &[(50., 0.), (50., 40.), (0., 40.), (0., 100.)]);
+        assert_eq!(
+            paths[1],
+            &[(50., 0.), (50., 40.), (100., 40.), (100., 100.)]
+        );
+        assert_eq!(paths[2], &[(200., 0.), (200., 100.)]);
+        assert_eq!(
+            paths[3],
+            &[(150., 0.), (150., 50.), (20., 50.), (20., 100.)]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use crate::read_dot::Rule;
+use pest::error::{ErrorVariant, InputLocation};
+use std::fmt::{self, Display};
+
+/// Position of an error in the input, `start` and `end` are byte offsets,
+/// `line` and `column` are 1-based and point to `start`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(data: &str, start: usize, end: usize) -> Self {
+        let before = &data[..start];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |n| n + 1);
+        Self {
+            start,
+            end,
+            line,
+            column: data[line_start..start].chars().count() + 1,
+        }
+    }
+
+    /// The input line containing the start of the span.
+    fn snippet(&self, data: &str) -> String {
+        let line_start = data[..self.start].rfind('\n').map_or(0, |n| n + 1);
+        let line_end = data[self.start..]
+            .find('\n')
+            .map_or(data.len(), |n| self.start + n);
+        data[line_start..line_end].trim_end().to_string()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// The input doesn't match the DOT grammar.
+    Syntax {
+        span: Span,
+        /// Human readable names of tokens allowed at the position.
+        expected: Vec<String>,
+        snippet: String,
+    },
+    /// `--` in a digraph or `->` in a graph.
+    EdgeOperator {
+        span: Span,
+        directed: bool,
+        snippet: String,
+    },
+}
+
+impl ParseError {
+    pub(crate) fn syntax(data: &str, error: &pest::error::Error<Rule>) -> Self {
+        let (start, end) = match error.location {
+            InputLocation::Pos(pos) => (pos, pos),
+            InputLocation::Span(span) => span,
+        };
+        let span = Span::new(data, start, end);
+        let mut expected: Vec<String> = match &error.variant {
+            ErrorVariant::ParsingError { positives, .. } => positives
+                .iter()
+                .map(|&rule| rule_name(rule).into())
+                .collect(),
+            ErrorVariant::CustomError { message } => vec![message.clone()],
+        };
+        let mut seen = std::collections::HashSet::new();
+        expected.retain(|e| seen.insert(e.clone()));
+        Self::Syntax {
+            snippet: span.snippet(data),
+            span,
+            expected,
+        }
+    }
+
+    pub(crate) fn edge_operator(data: &str, start: usize, end: usize, directed: bool) -> Self {
+        let span = Span::new(data, start, end);
+        Self::EdgeOperator {
+            snippet: span.snippet(data),
+            span,
+            directed,
+        }
+    }
+
+    pub fn span(&self) -> &Span {
+        match self {
+            Self::Syntax { span, .. } | Self::EdgeOperator { span, .. } => span,
+        }
+    }
+
+    fn snippet(&self) -> &str {
+        match self {
+            Self::Syntax { snippet, .. } | Self::EdgeOperator { snippet, .. } => snippet,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span = self.span();
+        write!(f, "{}:{}: ", span.line, span.column)?;
+        match self {
+            Self::Syntax { expected, .. } if expected.is_empty() => write!(f, "unexpected input")?,
+            Self::Syntax { expected, .. } => write!(f, "expected {}", expected.join(", "))?,
+            Self::EdgeOperator { directed: true, .. } => {
+                write!(f, "edge operator `--` is not allowed in digraph")?
+            }
+            Self::EdgeOperator {
+                directed: false, ..
+            } => write!(f, "edge operator `->` is not allowed in graph")?,
+        }
+        let marker_len = self
+            .snippet()
+            .chars()
+            .skip(span.column - 1)
+            .count()
+            .min(span.end - span.start)
+            .max(1);
+        write!(
+            f,
+            "\n  | {}\n  | {}{}",
+            self.snippet(),
+            " ".repeat(span.column - 1),
+            "^".repeat(marker_len)
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn rule_name(rule: Rule) -> &'static str {
+    match rule {
+        Rule::graph_kind => "`digraph` or `graph`",
+        Rule::statements | Rule::attribute_statement | Rule::link | Rule::node => "statement",
+        Rule::attribute_target => "`graph`, `node` or `edge`",
+        Rule::subgraph => "subgraph",
+        Rule::edge_op => "`->` or `--`",
+        Rule::attributes => "`[`",
+        Rule::attribute => "attribute",
+        Rule::id | Rule::node_id | Rule::quoted | Rule::numeral | Rule::name => "identifier",
+        Rule::port => "port",
+        Rule::EOI => "end of input",
+        _ => "token",
+    }
+}
@@ -1,7 +1,10 @@
-use rand::{prelude::ThreadRng, Rng};
-use std::fmt::Debug;
+use rand::{prelude::ThreadRng, rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+};
 
-use crate::graph::*;
+use crate::{cluster::Clusters, graph::*, layout::LayoutOptions, port::Ports};
 
 pub fn places<T: Debug>(graph: &DirectedGraph<T>, ranks: &NodeMap<i32>) -> NodeMap<u32> {
     let mut places = graph.node_map();
@@ -18,7 +21,13 @@ pub fn places<T: Debug>(graph: &DirectedGraph<T>, ranks: &NodeMap<i32>) -> NodeM
     places
 }
 
-pub fn places3<T: Debug>(graph: &DirectedGraph<T>, ranks: &NodeMap<i32>) -> NodeMap<u32> {
+pub fn places3<T: Debug>(
+    graph: &DirectedGraph<T>,
+    ranks: &NodeMap<i32>,
+    clusters: &Clusters,
+    ports: &Ports,
+    options: &LayoutOptions,
+) -> NodeMap<u32> {
     let mut layers = vec![];
 
     for (id, _) in graph.iter_nodes_with_id() {
@@ -30,13 +39,27 @@ pub fn places3<T: Debug>(graph: &DirectedGraph<T>, ranks: &NodeMap<i32>) -> Node
     }
 
     let mut places = places(graph, ranks);
-    let mut crosses = total_number_of_crosses(graph, &places, &layers);
+    if let Some(seed) = options.seed {
+        let mut rng = StdRng::seed_from_u64(seed);
+        for layer in &layers {
+            let mut order = layer.clone();
+            order.shuffle(&mut rng);
+            for (place, &id) in order.iter().enumerate() {
+                places.set(id, place as u32);
+            }
+        }
+    }
+    order_flat_edges(graph, ranks, &layers, &mut places);
+    group_clusters(&layers, &mut places, clusters);
+    let mut crosses = total_number_of_crosses(graph, &places, &layers, ports);
 
-    for i in 0..24 {
+    for i in 0..options.iterations {
         let mut candidate = places.clone();
-        wmedian(graph, &layers, &mut candidate, i % 2 == 0);
-        transpose(graph, &layers, &mut candidate);
-        let candidate_crosses = total_number_of_crosses(graph, &candidate, &layers);
+        wmedian(graph, &layers, &mut candidate, ports, i % 2 == 0);
+        transpose(graph, &layers, &mut candidate, ports);
+        order_flat_edges(graph, ranks, &layers, &mut candidate);
+        group_clusters(&layers, &mut candidate, clusters);
+        let candidate_crosses = total_number_of_crosses(graph, &candidate, &layers, ports);
         if candidate_crosses <= crosses {
             std::mem::swap(&mut places, &mut candidate);
             crosses = candidate_crosses;
@@ -52,15 +75,151 @@ pub fn places3<T: Debug>(graph: &DirectedGraph<T>, ranks: &NodeMap<i32>) -> Node
     places
 }
 
+/// Reorders every layer so flat edges, the ones with both ends on the layer,
+/// go from left to right and every head follows its tail if the tail has no
+/// other flat edges. Other nodes keep their order. Clusters are grouped
+/// afterwards, which keeps the order inside of every cluster.
+fn order_flat_edges<T: Debug>(
+    graph: &DirectedGraph<T>,
+    ranks: &NodeMap<i32>,
+    layers: &[Vec<NodeId>],
+    places: &mut NodeMap<u32>,
+) {
+    let is_flat = |&e: &EdgeId| {
+        let edge = graph.edge(e);
+        ranks.get(edge.from) == ranks.get(edge.to)
+    };
+    for layer in layers {
+        if !layer
+            .iter()
+            .any(|&id| graph.node(id).outputs.iter().any(is_flat))
+        {
+            continue;
+        }
+        let mut order = layer.clone();
+        order.sort_by_key(|&id| *places.get(id));
+        let mut placed = HashSet::new();
+        let mut ordered = Vec::with_capacity(order.len());
+        for &id in &order {
+            let mut stack = vec![id];
+            while let Some(id) = stack.pop() {
+                let node = graph.node(id);
+                let is_ready = |&e: &EdgeId| placed.contains(&graph.edge(e).from);
+                if placed.contains(&id) || !node.inputs.iter().filter(|e| is_flat(e)).all(is_ready)
+                {
+                    continue;
+                }
+                placed.insert(id);
+                ordered.push(id);
+                let mut heads: Vec<_> = node
+                    .outputs
+                    .iter()
+                    .filter(|e| is_flat(e))
+                    .map(|&e| graph.edge(e).to)
+                    .collect();
+                heads.sort_by_key(|&head| std::cmp::Reverse(*places.get(head)));
+                stack.extend(heads);
+            }
+        }
+        // Flat edges are acyclic, but a cycle would leave nodes out.
+        ordered.extend(order.iter().filter(|id| !placed.contains(*id)));
+        for (n, id) in ordered.into_iter().enumerate() {
+            places.set(id, n as u32);
+        }
+    }
+}
+
+/// Reorders every layer to keep nodes of each cluster next to each other.
+/// Groups are ordered by the mean place of their nodes, sibling clusters keep
+/// the same order on all layers, otherwise cluster boxes would overlap.
+fn group_clusters(layers: &[Vec<NodeId>], places: &mut NodeMap<u32>, clusters: &Clusters) {
+    if clusters.is_empty() {
+        return;
+    }
+
+    let mut sums = HashMap::<usize, (f64, u32)>::new();
+    for &id in layers.iter().flatten() {
+        for cluster in clusters.path(id) {
+            let (sum, count) = sums.entry(cluster).or_default();
+            *sum += *places.get(id) as f64;
+            *count += 1;
+        }
+    }
+    let cluster_order: HashMap<usize, f64> = sums
+        .into_iter()
+        .map(|(cluster, (sum, count))| (cluster, sum / count as f64))
+        .collect();
+
+    for layer in layers {
+        let mut items: Vec<_> = layer
+            .iter()
+            .map(|&id| (id, *places.get(id) as f64, clusters.path(id)))
+            .collect();
+        items.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let mut ordered = Vec::with_capacity(items.len());
+        arrange_groups(items, 0, &cluster_order, &mut ordered);
+        for (n, id) in ordered.into_iter().enumerate() {
+            places.set(id, n as u32);
+        }
+    }
+}
+
+fn arrange_groups(
+    items: Vec<(NodeId, f64, Vec<usize>)>,
+    depth: usize,
+    cluster_order: &HashMap<usize, f64>,
+    output: &mut Vec<NodeId>,
+) {
+    // (cluster, mean place, items), cluster is None for a node outside of clusters
+    let mut groups: Vec<(Option<usize>, f64, Vec<_>)> = vec![];
+    for item in items {
+        let cluster = item.2.get(depth).copied();
+        match groups
+            .iter_mut()
+            .find(|g| cluster.is_some() && g.0 == cluster)
+        {
+            Some(group) => group.2.push(item),
+            None => groups.push((cluster, 0., vec![item])),
+        }
+    }
+    for group in groups.iter_mut() {
+        group.1 = group.2.iter().map(|i| i.1).sum::<f64>() / group.2.len() as f64;
+    }
+    groups.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    // clusters take the slots of clusters in the global order
+    let slots: Vec<_> = (0..groups.len())
+        .filter(|&n| groups[n].0.is_some())
+        .collect();
+    let mut cluster_groups: Vec<_> = slots.iter().map(|&n| groups[n].0.unwrap()).collect();
+    cluster_groups.sort_by(|a, b| cluster_order[a].total_cmp(&cluster_order[b]));
+    let mut by_cluster: HashMap<_, _> = slots
+        .iter()
+        .map(|&n| (groups[n].0.unwrap(), std::mem::take(&mut groups[n].2)))
+        .collect();
+    for (&slot, cluster) in slots.iter().zip(cluster_groups) {
+        groups[slot].2 = by_cluster.remove(&cluster).unwrap();
+    }
+
+    for (cluster, _, items) in groups {
+        if cluster.is_some() {
+            arrange_groups(items, depth + 1, cluster_order, output);
+        } else {
+            output.extend(items.into_iter().map(|i| i.0));
+        }
+    }
+}
+
 fn wmedian<T: Debug>(
     graph: &DirectedGraph<T>,
     layers: &[Vec<NodeId>],
     candidate: &mut NodeMap<u32>,
+    ports: &Ports,
     top_down: bool,
 ) {
     if top_down {
-        for i in 1..layers.len() {
-            let mut medians = median(graph, candidate, &layers[i], true);
+        for layer in layers.iter().skip(1) {
+            let mut medians = median(graph, candidate, layer, ports, true);
             medians.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
             let mut next_free = 0;
             for &(node_id, m) in medians.iter() {
@@ -71,7 +230,7 @@ fn wmedian<T: Debug>(
         }
     } else {
         for i in (0..layers.len() - 1).rev() {
-            let mut medians = median(graph, candidate, &layers[i], false);
+            let mut medians = median(graph, candidate, &layers[i], ports, false);
             medians.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
             // dbg!(&medians);
             let mut next_free = 0;
@@ -89,25 +248,40 @@ fn median<T: Debug>(
     graph: &DirectedGraph<T>,
     places: &NodeMap<u32>,
     layer: &[NodeId],
+    ports: &Ports,
     from_top: bool,
 ) -> Vec<(NodeId, f64)> {
     let mut medians = vec![];
     for &id in layer {
-        let mut linked_places: Vec<u32> = if from_top {
-            graph.iter_parents(id).map(|p| *places.get(p)).collect()
+        // Ports shift the linked place by less than a half, so an edge from a
+        // left field pulls the node to the left of edges from right fields.
+        let node = graph.node(id);
+        let mut linked_places: Vec<f64> = if from_top {
+            node.inputs
+                .iter()
+                .map(|&e| {
+                    let (from, to) = ports.offsets(e);
+                    *places.get(graph.edge(e).from) as f64 + from - to
+                })
+                .collect()
         } else {
-            graph.iter_children(id).map(|c| *places.get(c)).collect()
+            node.outputs
+                .iter()
+                .map(|&e| {
+                    let (from, to) = ports.offsets(e);
+                    *places.get(graph.edge(e).to) as f64 + to - from
+                })
+                .collect()
         };
-        linked_places.sort();
+        linked_places.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
-        let med = if linked_places.len() == 0 {
+        let med = if linked_places.is_empty() {
             -1.
         } else if linked_places.len() % 2 == 1 {
-            linked_places[linked_places.len() / 2] as f64
+            linked_places[linked_places.len() / 2]
         } else {
             //TODO try to use formula from the DOC
-            (linked_places[linked_places.len() / 2 - 1] as f64
-                + linked_places[linked_places.len() / 2] as f64)
+            (linked_places[linked_places.len() / 2 - 1] + linked_places[linked_places.len() / 2])
                 / 2.
         };
         medians.push((id, med));
@@ -119,8 +293,9 @@ fn transpose<T: Debug>(
     graph: &DirectedGraph<T>,
     layers: &[Vec<NodeId>],
     candidate: &mut NodeMap<u32>,
+    ports: &Ports,
 ) {
-    let mut crosses = total_number_of_crosses(graph, candidate, layers);
+    let mut crosses = total_number_of_crosses(graph, candidate, layers, ports);
     loop {
         let mut improved = false;
         for layer in layers {
@@ -129,7 +304,7 @@ fn transpose<T: Debug>(
                 let node2_place = *candidate.get(layer[i + 1]);
                 candidate.set(layer[i], node2_place);
                 candidate.set(layer[i + 1], node1_place);
-                let new_crosses = total_number_of_crosses(graph, candidate, layers);
+                let new_crosses = total_number_of_crosses(graph, candidate, layers, ports);
                 if new_crosses < crosses {
                     crosses = new_crosses;
                     improved = true;
@@ -174,9 +349,9 @@ pub fn places2<T: Debug>(graph: &DirectedGraph<T>, ranks: &NodeMap<u32>) -> Node
 
     let other_layer = if biggest_layer == 0 && layers.len() > 1 {
         2
-    } else if biggest_layer == layers.len() - 1 {
-        biggest_layer - 1
-    } else if layers[biggest_layer - 1].len() >= layers[biggest_layer + 1].len() {
+    } else if biggest_layer == layers.len() - 1
+        || layers[biggest_layer - 1].len() >= layers[biggest_layer + 1].len()
+    {
         biggest_layer - 1
     } else {
         biggest_layer + 1
@@ -324,7 +499,7 @@ impl<'a, T: Debug> PlaceContext<'a, T> {
             .collect()
     }
 
-    fn select_next(&mut self, candidates: &mut Vec<(NodeId, (u32, u32))>) -> PossiblePlaces {
+    fn select_next(&mut self, candidates: &mut [(NodeId, (u32, u32))]) -> PossiblePlaces {
         candidates.sort_by_key(|(_, (_, c))| *c);
         let max = (candidates.last().unwrap().1).1 as f64;
         let from = max * 2.0 / 3.0;
@@ -350,17 +525,16 @@ impl<'a, T: Debug> PlaceContext<'a, T> {
         self.candidates_vec.remove(next_position);
     }
 
-    fn calculate_place(&mut self, places_iter: PossiblePlaces) {
+    fn calculate_place(&mut self, mut places_iter: PossiblePlaces) {
         //TODO prevent infinity loop in case of error
         self.place = places_iter
-            .filter(|&place| {
+            .find(|&place| {
                 if self.is_upper {
                     place < self.used_top_places.len() && !self.used_top_places[place]
                 } else {
                     place < self.used_bottom_places.len() && !self.used_bottom_places[place]
                 }
             })
-            .next()
             .unwrap();
     }
 
@@ -404,11 +578,10 @@ fn place_layers<T: Debug>(
             let top_top_node_id = c.top_id_and_count();
             let top_bottom_node_id = c.bottom_id_and_count();
 
-            if top_bottom_node_id.is_none()
-                || (top_top_node_id.is_some()
-                    && top_top_node_id.unwrap().1 >= top_bottom_node_id.unwrap().1)
+            if let Some((next, _)) = top_top_node_id
+                .filter(|top| top_bottom_node_id.is_none_or(|bottom| top.1 >= bottom.1))
             {
-                c.next = top_top_node_id.unwrap().0;
+                c.next = next;
                 c.is_upper = true;
             } else {
                 c.next = top_bottom_node_id.unwrap().0;
@@ -479,6 +652,18 @@ fn number_of_crosses<T: Debug>(
     top_layer: &[NodeId],
     bottom_layer: &[NodeId],
     places: &NodeMap<u32>,
+) -> u32 {
+    number_of_port_crosses(graph, top_layer, bottom_layer, places, &Ports::default())
+}
+
+/// Edges of one top node are ordered by their ports, edges from a left field
+/// to a right node cross the ones from a right field to a left node.
+fn number_of_port_crosses<T: Debug>(
+    graph: &DirectedGraph<T>,
+    top_layer: &[NodeId],
+    bottom_layer: &[NodeId],
+    places: &NodeMap<u32>,
+    ports: &Ports,
 ) -> u32 {
     let mut is_in_bottom = graph.node_map::<bool>();
     let mut crosses = 0;
@@ -500,13 +685,15 @@ fn number_of_crosses<T: Debug>(
 
     for (from_id, _) in top_ordered_id_and_place {
         let mut to_places: Vec<_> = graph
-            .iter_children(from_id)
-            .filter(|&to_id| *is_in_bottom.get(to_id))
-            .map(|id| *places.get(id) as usize)
+            .node(from_id)
+            .outputs
+            .iter()
+            .filter(|&&e| *is_in_bottom.get(graph.edge(e).to))
+            .map(|&e| (ports.offsets(e).0, *places.get(graph.edge(e).to) as usize))
             .collect();
-        to_places.sort();
+        to_places.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
-        for place in to_places {
+        for (_, place) in to_places {
             bottom_links_count[place] += 1;
             crosses += &bottom_links_count[place + 1..].iter().sum();
         }
@@ -518,9 +705,12 @@ fn total_number_of_crosses<T: Debug>(
     graph: &DirectedGraph<T>,
     places: &NodeMap<u32>,
     layers: &[Vec<NodeId>],
+    ports: &Ports,
 ) -> u32 {
     (1..layers.len())
-        .map(|bottom| number_of_crosses(graph, &layers[bottom - 1], &layers[bottom], places))
+        .map(|bottom| {
+            number_of_port_crosses(graph, &layers[bottom - 1], &layers[bottom], places, ports)
+        })
         .sum()
 }
 
@@ -534,7 +724,7 @@ impl PossiblePlaces {
     fn new(sum: u32, count: u32) -> Self {
         Self {
             start: sum / count.max(1),
-            current: u32::max_value(),
+            current: u32::MAX,
         }
     }
 }
@@ -543,7 +733,7 @@ impl Iterator for PossiblePlaces {
     type Item = usize;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.current = if self.current == u32::max_value() {
+        self.current = if self.current == u32::MAX {
             self.start
         } else if self.current <= self.start {
             self.start - self.current + self.start + 1
@@ -671,6 +861,86 @@ mod tests {
         dbg!(&places);
     }
 
+    #[test]
+    fn ports_order() {
+        let dot = crate::read_dot::parse(
+            "digraph { a [shape=record, label=\"<l>|<r>\"]; a:r -> b; a:l -> c }",
+        )
+        .unwrap();
+        let mut ranks = dot.graph.node_map();
+        ranks.set(NodeId::from(1u32), 1);
+        ranks.set(NodeId::from(2u32), 1);
+        let p = places3(
+            &dot.graph,
+            &ranks,
+            &Clusters::default(),
+            &Ports::new(&dot, Default::default()),
+            &LayoutOptions::default(),
+        );
+        assert!(p.get(NodeId::from(2u32)) < p.get(NodeId::from(1u32)));
+        let p = places3(
+            &dot.graph,
+            &ranks,
+            &Clusters::default(),
+            &Ports::default(),
+            &LayoutOptions::default(),
+        );
+        assert!(p.get(NodeId::from(1u32)) < p.get(NodeId::from(2u32)));
+    }
+
+    #[test]
+    fn flat_edges() {
+        // Heads follow their tails, node 1 has no flat edges and stays first.
+        let graph = DirectedGraph::new(&[0, 1, 2, 3], &[(2, 0), (2, 3), (3, 0)]);
+        let ranks = graph.node_map();
+        let layers = vec![(0..4u32).map(NodeId::from).collect::<Vec<_>>()];
+        let mut places = places(&graph, &ranks);
+        order_flat_edges(&graph, &ranks, &layers, &mut places);
+        let order: Vec<_> = (0..4u32).map(|n| *places.get(NodeId::from(n))).collect();
+        assert_eq!(order, &[3, 0, 1, 2]);
+
+        let p = places3(
+            &graph,
+            &ranks,
+            &Clusters::default(),
+            &Ports::default(),
+            &LayoutOptions::default(),
+        );
+        assert!(p.get(NodeId::from(2u32)) < p.get(NodeId::from(3u32)));
+        assert!(p.get(NodeId::from(3u32)) < p.get(NodeId::from(0u32)));
+    }
+
+    #[test]
+    fn seed() {
+        let graph = DirectedGraph::new(&[0], &[(0, 1), (0, 2), (0, 3), (1, 4), (2, 5), (3, 6)]);
+        let ranks = crate::rank_with_components(&graph);
+        let options = LayoutOptions {
+            seed: Some(7),
+            iterations: 0,
+            ..LayoutOptions::default()
+        };
+        let shuffled = places3(
+            &graph,
+            &ranks,
+            &Clusters::default(),
+            &Ports::default(),
+            &options,
+        );
+        let again = places3(
+            &graph,
+            &ranks,
+            &Clusters::default(),
+            &Ports::default(),
+            &options,
+        );
+        assert_eq!(shuffled, again);
+        let mut places: Vec<_> = (1..4)
+            .map(|n| *shuffled.get(NodeId::from(n as u32)))
+            .collect();
+        places.sort();
+        assert_eq!(places, &[0, 1, 2]);
+    }
+
     #[test]
     fn iterator() {
         let mut i = PossiblePlaces::new(2, 1);
@@ -0,0 +1,201 @@
+use crate::graph::*;
+use crate::layout::RankDir;
+use crate::read_dot::DotGraph;
+use crate::record::{self, FieldBox};
+use std::collections::HashMap;
+
+/// Point of an edge end relative to the node box, (0, 0) is the top left
+/// corner and (1, 1) is the bottom right one. Ports are kept in the top to
+/// bottom layout, where `edge.from` is above `edge.to`.
+pub type PortPoint = (f64, f64);
+
+/// Edge ends attached to record fields or compass points by `tailport` and
+/// `headport`, ends without a port stay in the middle of the node side.
+#[derive(Clone, Debug, Default)]
+pub struct Ports {
+    /// Points at `edge.from` and `edge.to` of edges with ports, after
+    /// inversion the head port is at `edge.from`.
+    ends: HashMap<EdgeId, (Option<PortPoint>, Option<PortPoint>)>,
+}
+
+impl Ports {
+    /// Must be called after ranking, when edges have their final direction.
+    /// Record fields and compass points are turned from `rankdir` to the top
+    /// to bottom layout.
+    pub fn new(dot: &DotGraph, rankdir: RankDir) -> Self {
+        let mut ends = HashMap::new();
+        let fields: NodeMap<Option<Vec<FieldBox>>> = {
+            let mut fields = dot.graph.node_map();
+            for id in dot.graph.iter_nodes_ids() {
+                let attributes = dot.node_attributes.get(id);
+                let label = dot
+                    .label(id)
+                    .or_else(|| dot.graph.original_id(id).map(|v| v.as_ref()))
+                    .unwrap_or_default();
+                let horizontal = !rankdir.is_horizontal();
+                if let Some((mut boxes, _)) = record::node_fields(attributes, label, horizontal) {
+                    for b in &mut boxes {
+                        let (x1, y1) = rankdir.to_frame((b.x, b.y), (1., 1.));
+                        let (x2, y2) = rankdir.to_frame((b.x + b.width, b.y + b.height), (1., 1.));
+                        (b.x, b.y) = (x1.min(x2), y1.min(y2));
+                        (b.width, b.height) = ((x2 - x1).abs(), (y2 - y1).abs());
+                    }
+                    fields.set(id, Some(boxes));
+                }
+            }
+            fields
+        };
+        for (edge_id, attributes) in dot.edge_attributes.iter() {
+            let edge = dot.graph.edge(edge_id);
+            let (tail, head) = (attributes.get("tailport"), attributes.get("headport"));
+            let (from_port, to_port) = if edge.is_inverted() {
+                (head, tail)
+            } else {
+                (tail, head)
+            };
+            let point =
+                |port, id, is_from| point(port, fields.get(id).as_deref(), is_from, rankdir);
+            let from = from_port.and_then(|p| point(p, edge.from, true));
+            let to = to_port.and_then(|p| point(p, edge.to, false));
+            if from.is_some() || to.is_some() {
+                ends.insert(edge_id, (from, to));
+            }
+        }
+        Ports { ends }
+    }
+
+    /// Moves the `edge.to` end of split edges to the last edge of the chain,
+    /// must be called after [`crate::add_virtual_nodes::add_virtual_nodes`].
+    pub fn add_virtual_nodes<T>(&mut self, graph: &DirectedGraph<T>, original_edges_count: u32) {
+        if self.is_empty() {
+            return;
+        }
+        for n in 0..original_edges_count {
+            let edge_id = EdgeId::from(n);
+            let mut last = edge_id;
+            while graph.node(graph.edge(last).to).is_virtual {
+                last = graph.node(graph.edge(last).to).outputs[0];
+            }
+            if last == edge_id {
+                continue;
+            }
+            if let Some(to) = self.ends.get_mut(&edge_id).and_then(|e| e.1.take()) {
+                self.ends.insert(last, (None, Some(to)));
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// Port points at `edge.from` and `edge.to`.
+    pub fn ends(&self, id: EdgeId) -> (Option<PortPoint>, Option<PortPoint>) {
+        self.ends.get(&id).copied().unwrap_or_default()
+    }
+
+    /// Horizontal shifts of the edge ends from the node centers in node
+    /// widths, used to order edges going to different fields.
+    pub fn offsets(&self, id: EdgeId) -> (f64, f64) {
+        let (from, to) = self.ends(id);
+        (
+            from.map_or(0., |(x, _)| x - 0.5),
+            to.map_or(0., |(x, _)| x - 0.5),
+        )
+    }
+}
+
+/// Resolves `field:compass`, `field` or `compass`, `fields` are already in
+/// the top to bottom layout. A field without a compass point is attached to
+/// the bottom side of the node at `edge.from` and to the top side at
+/// `edge.to`, unknown names are ignored like in Graphviz.
+fn point(
+    port: &str,
+    fields: Option<&[FieldBox]>,
+    is_from: bool,
+    rankdir: RankDir,
+) -> Option<PortPoint> {
+    let find = |name: &str| fields.and_then(|f| f.iter().find(|b| b.port.as_deref() == Some(name)));
+    let (field, compass) = match port.rsplit_once(':') {
+        Some((name, compass)) => (Some(find(name)?), compass),
+        None => match find(port) {
+            Some(field) => (Some(field), ""),
+            None => (None, port),
+        },
+    };
+    let (x, y, width, height) = field.map_or((0., 0., 1., 1.), |b| (b.x, b.y, b.width, b.height));
+    let (cx, cy) = match compass {
+        "n" => (0.5, 0.),
+        "ne" => (1., 0.),
+        "e" => (1., 0.5),
+        "se" => (1., 1.),
+        "s" => (0.5, 1.),
+        "sw" => (0., 1.),
+        "w" => (0., 0.5),
+        "nw" => (0., 0.),
+        "c" | "_" | "" if field.is_some() => {
+            return Some((x + width / 2., if is_from { 1. } else { 0. }));
+        }
+        "c" => (0.5, 0.5),
+        _ => return None,
+    };
+    let (cx, cy) = rankdir.to_frame((cx, cy), (1., 1.));
+    Some((x + cx * width, y + cy * height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{add_virtual_nodes::add_virtual_nodes, read_dot::parse};
+
+    #[test]
+    fn points() {
+        let fields = record::parse("<a> x|{<b> y|<c> z}")
+            .unwrap()
+            .layout(true, &|text: &str| (text.len() as f64, 1.));
+        let fields = Some(&fields[..]);
+        let top_bottom = RankDir::TopToBottom;
+        assert_eq!(point("a", fields, true, top_bottom), Some((0.25, 1.)));
+        assert_eq!(point("c", fields, false, top_bottom), Some((0.75, 0.)));
+        assert_eq!(point("b:e", fields, true, top_bottom), Some((1., 0.25)));
+        assert_eq!(point("n", fields, true, top_bottom), Some((0.5, 0.)));
+        assert_eq!(point("sw", None, true, top_bottom), Some((0., 1.)));
+        assert_eq!(point("x", fields, true, top_bottom), None);
+        assert_eq!(point("x:n", fields, true, top_bottom), None);
+        // The east side goes down in the left to right layout.
+        assert_eq!(
+            point("e", None, true, RankDir::LeftToRight),
+            Some((0.5, 1.))
+        );
+        assert_eq!(
+            point("e", None, true, RankDir::RightToLeft),
+            Some((0.5, 0.))
+        );
+        assert_eq!(
+            point("n", None, true, RankDir::BottomToTop),
+            Some((0.5, 1.))
+        );
+    }
+
+    #[test]
+    fn virtual_nodes() {
+        let mut dot = parse(
+            "digraph { node [shape=record]; a [label=\"<l>|<r>\"]; a:r -> b:n; a:l -> c; b -> c }",
+        )
+        .unwrap();
+        let mut ranks = dot.graph.node_map();
+        ranks.set(NodeId::from(1u32), 1);
+        ranks.set(NodeId::from(2u32), 2);
+        let edges_count = dot.graph.edges_count();
+        let mut ports = Ports::new(&dot, RankDir::TopToBottom);
+        add_virtual_nodes(&mut dot.graph, &mut ranks);
+        ports.add_virtual_nodes(&dot.graph, edges_count);
+        assert_eq!(
+            ports.ends(EdgeId::from(0u32)),
+            (Some((0.75, 1.)), Some((0.5, 0.)))
+        );
+        assert_eq!(ports.ends(EdgeId::from(1u32)), (Some((0.25, 1.)), None));
+        assert_eq!(ports.ends(EdgeId::from(3u32)), (None, None));
+        assert_eq!(ports.offsets(EdgeId::from(1u32)), (-0.25, 0.));
+    }
+}
@@ -0,0 +1,273 @@
+use crate::graph::*;
+use crate::read_dot::Subgraph;
+use crate::{rank_with_components, to_dag::to_dag};
+use std::fmt::Debug;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RankKind {
+    Same,
+    Min,
+    Max,
+    Source,
+    Sink,
+}
+
+impl RankKind {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "same" => Some(Self::Same),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            "source" => Some(Self::Source),
+            "sink" => Some(Self::Sink),
+            _ => None,
+        }
+    }
+}
+
+/// Nodes of a subgraph with the `rank` attribute.
+#[derive(Clone, Debug)]
+pub struct RankConstraint {
+    pub kind: RankKind,
+    pub nodes: Vec<NodeId>,
+}
+
+/// Collects constraints from the subgraph tree, unknown `rank` values are
+/// ignored.
+pub fn rank_constraints(subgraphs: &[Subgraph]) -> Vec<RankConstraint> {
+    let mut constraints = vec![];
+    for subgraph in subgraphs {
+        if let Some(kind) = subgraph
+            .attributes
+            .get("rank")
+            .and_then(|v| RankKind::parse(v))
+        {
+            if !subgraph.nodes.is_empty() {
+                constraints.push(RankConstraint {
+                    kind,
+                    nodes: subgraph.nodes.clone(),
+                });
+            }
+        }
+        constraints.extend(rank_constraints(&subgraph.subgraphs));
+    }
+    constraints
+}
+
+/// Ranks the DAG honoring the constraints. Nodes of every set are merged into
+/// a single node, `min`/`source` sets get an edge to all other nodes and all
+/// other nodes get an edge to `max`/`sink` sets. Cycles created by merging are
+/// broken and the expanded ranks are applied back: edges going up are inverted,
+/// edges inside of a set become flat. Edges with `constraint=false` are skipped.
+pub fn rank<T: Debug>(
+    graph: &mut DirectedGraph<T>,
+    constraints: &[RankConstraint],
+) -> NodeMap<i32> {
+    if constraints.is_empty() && graph.iter_edges().all(|e| e.constraint) {
+        return rank_with_components(graph);
+    }
+
+    // Union-find parents, None for set leaders.
+    let mut leaders = graph.node_map();
+    let mut min_set = None;
+    let mut max_set = None;
+    let mut is_source = false;
+    let mut is_sink = false;
+    for constraint in constraints {
+        let first = constraint.nodes[0];
+        for &id in &constraint.nodes[1..] {
+            union(&mut leaders, first, id);
+        }
+        let extreme = match constraint.kind {
+            RankKind::Same => continue,
+            RankKind::Min | RankKind::Source => {
+                is_source |= constraint.kind == RankKind::Source;
+                &mut min_set
+            }
+            RankKind::Max | RankKind::Sink => {
+                is_sink |= constraint.kind == RankKind::Sink;
+                &mut max_set
+            }
+        };
+        match *extreme {
+            Some(other) => union(&mut leaders, other, first),
+            None => *extreme = Some(first),
+        }
+    }
+
+    let mut merged = DirectedGraph::<()>::default();
+    let mut merged_ids = graph.node_map::<Option<NodeId>>();
+    for id in graph.iter_nodes_ids() {
+        let leader = find(&mut leaders, id);
+        if merged_ids.get(leader).is_none() {
+            merged_ids.set(leader, Some(merged.add_node(Node::default())));
+        }
+        let merged_id = *merged_ids.get(leader);
+        merged_ids.set(id, merged_id);
+    }
+    let merged_id = |id| merged_ids.get(id).unwrap();
+
+    // Cycles are broken again from the input directions, because the ones
+    // broken before could go through ignored edges. Edges into the min set and
+    // out of the max set are reversed, so extra edges added later can't create
+    // new cycles.
+    let min = min_set.map(merged_id);
+    let max = max_set.map(merged_id).filter(|&max| Some(max) != min);
+    for edge in graph.iter_edges().filter(|e| e.constraint) {
+        let (mut from, mut to) = (merged_id(edge.from), merged_id(edge.to));
+        if edge.is_inverted() {
+            std::mem::swap(&mut from, &mut to);
+        }
+        if Some(to) == min || Some(from) == max {
+            std::mem::swap(&mut from, &mut to);
+        }
+        if from != to {
+            add_edge(&mut merged, from, to, edge.min_length, edge.weight);
+        }
+    }
+    to_dag(&mut merged);
+
+    let mut constrained = DirectedGraph::<()>::default();
+    for _ in merged.iter_nodes_ids() {
+        constrained.add_node(Node::default());
+    }
+    for edge in merged.iter_edges() {
+        add_edge(
+            &mut constrained,
+            edge.from,
+            edge.to,
+            edge.min_length,
+            edge.weight,
+        );
+    }
+    for id in merged.iter_nodes_ids() {
+        if let Some(min) = min.filter(|&min| min != id) {
+            add_edge(&mut constrained, min, id, is_source as u32, 0);
+        }
+        if let Some(max) = max.filter(|&max| max != id) {
+            add_edge(&mut constrained, id, max, is_sink as u32, 0);
+        }
+    }
+    for id in merged.iter_nodes_ids() {
+        if constrained.node(id).inputs.is_empty() {
+            constrained.add_root(id);
+        }
+    }
+
+    let merged_ranks = rank_with_components(&constrained);
+
+    let mut ranks = graph.node_map();
+    for id in graph.iter_nodes_ids() {
+        ranks.set(id, *merged_ranks.get(merged_id(id)));
+    }
+
+    let upward: Vec<_> = graph
+        .iter_edges_with_id()
+        .filter(|(_, edge)| ranks.get(edge.from) > ranks.get(edge.to))
+        .map(|(id, _)| id)
+        .collect();
+    for edge_id in upward {
+        let edge = graph.edge_mut(edge_id);
+        let (from, to) = (edge.from, edge.to);
+        edge.invert();
+        graph.node_mut(from).to_input(edge_id);
+        graph.node_mut(to).to_output(edge_id);
+    }
+    ranks
+}
+
+fn find(leaders: &mut NodeMap<Option<NodeId>>, id: NodeId) -> NodeId {
+    let Some(parent) = *leaders.get(id) else {
+        return id;
+    };
+    let leader = find(leaders, parent);
+    leaders.set(id, Some(leader));
+    leader
+}
+
+fn union(leaders: &mut NodeMap<Option<NodeId>>, a: NodeId, b: NodeId) {
+    let a = find(leaders, a);
+    let b = find(leaders, b);
+    if a != b {
+        leaders.set(b, Some(a));
+    }
+}
+
+fn add_edge(graph: &mut DirectedGraph<()>, from: NodeId, to: NodeId, min_length: u32, weight: i32) {
+    let edge_id = graph.add_edge(Edge {
+        from,
+        to,
+        kind: EdgeKind::Normal,
+        min_length,
+        weight,
+        constraint: true,
+    });
+    graph.node_mut(from).outputs.push(edge_id);
+    graph.node_mut(to).inputs.push(edge_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_dot::parse;
+
+    fn ranks(input: &str) -> Vec<i32> {
+        let mut dot = parse(input).unwrap();
+        to_dag(&mut dot.graph);
+        let constraints = rank_constraints(&dot.subgraphs);
+        let ranks = rank(&mut dot.graph, &constraints);
+        ranks.iter().map(|(_, &r)| r).collect()
+    }
+
+    #[test]
+    fn same() {
+        assert_eq!(
+            ranks("digraph { a -> b -> c; a -> d; {rank=same; c; d} }"),
+            &[0, 1, 2, 2]
+        );
+    }
+
+    #[test]
+    fn same_inverts_edges() {
+        let mut dot = parse("digraph { a -> b -> c; {rank=same; a; c} }").unwrap();
+        to_dag(&mut dot.graph);
+        let constraints = rank_constraints(&dot.subgraphs);
+        let ranks = rank(&mut dot.graph, &constraints);
+        assert_eq!(
+            *ranks.get(NodeId::from(0u32)),
+            *ranks.get(NodeId::from(2u32))
+        );
+        for edge in dot.graph.iter_edges() {
+            assert!(ranks.get(edge.from) <= ranks.get(edge.to));
+        }
+        assert_eq!(
+            dot.graph.iter_edges().filter(|e| e.is_inverted()).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn min_and_max() {
+        assert_eq!(
+            ranks("digraph { a -> b -> c; d -> e; {rank=min; e} {rank=max; d} }"),
+            &[0, 1, 2, 2, 0]
+        );
+    }
+
+    #[test]
+    fn edge_params() {
+        assert_eq!(
+            ranks("digraph { a -> b [minlen=2]; b -> c [constraint=false]; c -> a }"),
+            &[1, 3, 0]
+        );
+        assert_eq!(ranks("digraph { a -> b [minlen=0] }"), &[0, 0]);
+    }
+
+    #[test]
+    fn source_and_sink() {
+        assert_eq!(
+            ranks("digraph { a -> b; c -> d; {rank=source; a; c} {rank=sink; b} }"),
+            &[0, 2, 0, 1]
+        );
+    }
+}
@@ -1,94 +1,916 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use crate::graph::*;
+use crate::parse_error::ParseError;
 use pest::{iterators::Pair, Parser};
 
 #[derive(pest_derive::Parser)]
 #[grammar = "dot.pest"]
 struct DotParser;
 
+/// Attribute values by name, sorted to make output stable.
+pub type Attributes<'a> = BTreeMap<Cow<'a, str>, Cow<'a, str>>;
+
 #[derive(Clone)]
 pub struct DotGraph<'a> {
-    pub graph: DirectedGraph<&'a str>,
-    pub labels: NodeMap<Option<&'a str>>,
+    /// Node ids are borrowed from the input unless they are quoted strings
+    /// with escapes or concatenation.
+    pub graph: DirectedGraph<Cow<'a, str>>,
+    pub name: Option<Cow<'a, str>>,
+    /// `digraph` if true, `graph` otherwise, edges of undirected graph are
+    /// laid out from the first node to the second but drawn without arrows.
+    pub directed: bool,
+    /// `strict` graph, duplicated edges are collapsed to one.
+    pub strict: bool,
+    /// Graph attributes from `graph [...]` and `name=value` statements.
+    pub attributes: Attributes<'a>,
+    pub node_attributes: NodeMap<Attributes<'a>>,
+    /// Attributes of the input edges, virtual edges have no attributes.
+    pub edge_attributes: EdgeMap<Attributes<'a>>,
+    /// Attributes of [`DirectedGraph::iter_self_edges`] in the same order.
+    pub self_edge_attributes: Vec<Attributes<'a>>,
+    /// Top level subgraphs, including anonymous `{ ... }` blocks.
+    pub subgraphs: Vec<Subgraph<'a>>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Subgraph<'a> {
+    pub name: Option<Cow<'a, str>>,
+    pub attributes: Attributes<'a>,
+    /// Nodes mentioned in the subgraph and all nested subgraphs in order of
+    /// appearance.
+    pub nodes: Vec<NodeId>,
+    pub subgraphs: Vec<Subgraph<'a>>,
+    /// The subgraph is an operand of an edge statement, like `{b c}` in
+    /// `a -> {b c}`.
+    pub edge_operand: bool,
+}
+
+impl Subgraph<'_> {
+    pub fn is_cluster(&self) -> bool {
+        self.name
+            .as_ref()
+            .is_some_and(|name| name.starts_with("cluster"))
+            || self.attributes.get("cluster").is_some_and(|v| v == "true")
+    }
+
+    fn map_nodes(&self, map: &NodeMap<Option<NodeId>>) -> Self {
+        Subgraph {
+            name: self.name.clone(),
+            attributes: self.attributes.clone(),
+            nodes: self.nodes.iter().filter_map(|&id| *map.get(id)).collect(),
+            subgraphs: self.subgraphs.iter().map(|s| s.map_nodes(map)).collect(),
+            edge_operand: self.edge_operand,
+        }
+    }
 }
 
 impl<'a> DotGraph<'a> {
+    pub fn label(&self, id: NodeId) -> Option<&str> {
+        self.node_attributes
+            .get(id)
+            .get("label")
+            .map(|v| v.as_ref())
+    }
+
     pub fn map_to_new(
         &self,
-        mut new: DirectedGraph<&'a str>,
+        mut new: DirectedGraph<Cow<'a, str>>,
         map: NodeMap<Option<NodeId>>,
+        edge_map: EdgeMap<Option<EdgeId>>,
     ) -> DotGraph<'a> {
-        let mut new_labels = new.node_map();
+        let mut node_attributes = new.node_map();
         for (old, opt_new) in map.iter() {
             if let &Some(new_id) = opt_new {
-                new_labels.set(new_id, self.labels.get(old).to_owned());
-                new.set_original_id(new_id, self.graph.original_id(old).unwrap())
+                node_attributes.set(new_id, self.node_attributes.get(old).clone());
+                new.set_original_id(new_id, self.graph.original_id(old).unwrap().clone())
+            }
+        }
+        let mut edge_attributes = new.edge_map();
+        for (old, opt_new) in edge_map.iter() {
+            if let &Some(new_id) = opt_new {
+                edge_attributes.set(new_id, self.edge_attributes.get(old).clone());
+            }
+        }
+        // Loops of kept nodes are kept.
+        let mut self_edge_attributes = vec![];
+        for (&old, attributes) in self.graph.iter_self_edges().zip(&self.self_edge_attributes) {
+            if let &Some(new_id) = map.get(old) {
+                new.add_self_edge(new_id);
+                self_edge_attributes.push(attributes.clone());
             }
         }
         DotGraph {
             graph: new,
-            labels: new_labels,
+            name: self.name.clone(),
+            directed: self.directed,
+            strict: self.strict,
+            attributes: self.attributes.clone(),
+            node_attributes,
+            edge_attributes,
+            self_edge_attributes,
+            subgraphs: self.subgraphs.iter().map(|s| s.map_nodes(&map)).collect(),
         }
     }
 }
 
-pub fn parse<'a>(data: &'a str) -> Result<DotGraph<'a>, String> {
-    let graph = DotParser::parse(Rule::graph, &data)
-        .map_err(|e| e.to_string())?
-        .next()
-        .unwrap();
-
-    Ok(convert_graph(graph))
+/// Parses DOT input, all found errors are returned in order of appearance.
+pub fn parse(data: &str) -> Result<DotGraph<'_>, Vec<ParseError>> {
+    match DotParser::parse(Rule::graph, data) {
+        Ok(mut pairs) => convert_graph(data, pairs.next().unwrap()),
+        Err(e) => Err(syntax_errors(data, e)),
+    }
 }
 
-fn convert_graph<'a>(graph: Pair<'a, Rule>) -> DotGraph<'a> {
-    // println!("Rule:    {:?}", graph.as_rule());
-    //println!("Text:    {}\n", graph.as_str());
-    let mut ids = vec![];
-    let mut labels = HashMap::new();
-    let mut links = vec![];
+const MAX_ERRORS: usize = 10;
 
-    for statement in graph.into_inner().skip(1).next().unwrap().into_inner() {
-        match statement.as_rule() {
-            Rule::link => links.push(link(statement)),
-            Rule::node => {
-                let (id, label) = node(statement);
-                ids.push(id);
-                if let Some(label) = label {
-                    labels.insert(id, label);
+/// Looks for more errors after the first one by blanking the failed statement
+/// and parsing again, positions stay the same because the length is kept.
+fn syntax_errors(data: &str, error: pest::error::Error<Rule>) -> Vec<ParseError> {
+    let mut errors = vec![ParseError::syntax(data, &error)];
+    let mut input = data.to_string();
+    while errors.len() < MAX_ERRORS {
+        let position = errors.last().unwrap().span().start;
+        // errors in the header or at the end can't be skipped
+        if !input[..position].contains('{') || position == input.len() {
+            break;
+        }
+        let start = input[..position]
+            .rfind([';', '{', '}', '\n'])
+            .map_or(0, |n| n + 1);
+        let end = input[position..]
+            .find([';', '\n'])
+            .map_or(input.len(), |n| position + n + 1);
+        input.replace_range(start..end, &" ".repeat(end - start));
+        match DotParser::parse(Rule::graph, &input) {
+            Ok(_) => break,
+            Err(e) => {
+                let error = ParseError::syntax(data, &e);
+                if error.span().start < end {
+                    break;
                 }
+                errors.push(error);
             }
+        }
+    }
+    errors
+}
+
+fn convert_graph<'a>(
+    data: &'a str,
+    graph: Pair<'a, Rule>,
+) -> Result<DotGraph<'a>, Vec<ParseError>> {
+    let mut builder = GraphBuilder::new(data);
+    let mut name = None;
+
+    for item in graph.into_inner() {
+        match item.as_rule() {
+            Rule::strict => builder.strict = true,
+            Rule::graph_kind => builder.directed = item.as_str().eq_ignore_ascii_case("digraph"),
+            Rule::id => name = Some(id(item)),
+            Rule::statements => {
+                builder.statements(item);
+            }
+            Rule::EOI => {}
             _ => unreachable!(),
         }
     }
-    let g = DirectedGraph::new(&ids, &links);
-    let mut map = g.node_map();
-    for (id, _) in g.iter_nodes_with_id() {
-        if let Some(&label) = g.original_id(id).and_then(|&id| labels.get(id)) {
-            map.set(id, Some(label))
+    if builder.errors.is_empty() {
+        Ok(builder.build(name))
+    } else {
+        Err(builder.errors)
+    }
+}
+
+/// Collects nodes in order of the first appearance and edges while walking
+/// the parse tree.
+struct GraphBuilder<'a> {
+    data: &'a str,
+    errors: Vec<ParseError>,
+    ids: Vec<Cow<'a, str>>,
+    node_attributes: HashMap<Cow<'a, str>, Attributes<'a>>,
+    links: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+    link_attributes: Vec<Attributes<'a>>,
+    seen_links: HashMap<(Cow<'a, str>, Cow<'a, str>), usize>,
+    scopes: Vec<Scope<'a>>,
+    directed: bool,
+    strict: bool,
+}
+
+/// Attributes set by `graph`, `node` and `edge` statements, each subgraph
+/// starts with a copy of the outer node and edge defaults.
+#[derive(Default)]
+struct Scope<'a> {
+    graph: Attributes<'a>,
+    node: Attributes<'a>,
+    edge: Attributes<'a>,
+    subgraphs: Vec<ParsedSubgraph<'a>>,
+}
+
+/// [`Subgraph`] before nodes get their ids.
+struct ParsedSubgraph<'a> {
+    name: Option<Cow<'a, str>>,
+    attributes: Attributes<'a>,
+    nodes: Vec<Cow<'a, str>>,
+    subgraphs: Vec<ParsedSubgraph<'a>>,
+    edge_operand: bool,
+}
+
+impl<'a> ParsedSubgraph<'a> {
+    fn into_subgraph(self, ids: &HashMap<Cow<str>, NodeId>) -> Subgraph<'a> {
+        Subgraph {
+            name: self.name,
+            attributes: self.attributes,
+            nodes: self.nodes.iter().map(|id| ids[id]).collect(),
+            subgraphs: self
+                .subgraphs
+                .into_iter()
+                .map(|s| s.into_subgraph(ids))
+                .collect(),
+            edge_operand: self.edge_operand,
+        }
+    }
+}
+
+impl<'a> GraphBuilder<'a> {
+    fn new(data: &'a str) -> Self {
+        Self {
+            data,
+            errors: vec![],
+            ids: vec![],
+            node_attributes: HashMap::new(),
+            links: vec![],
+            link_attributes: vec![],
+            seen_links: HashMap::new(),
+            scopes: vec![Scope::default()],
+            directed: true,
+            strict: false,
+        }
+    }
+
+    fn scope(&mut self) -> &mut Scope<'a> {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn add_node(&mut self, id: Cow<'a, str>) -> &mut Attributes<'a> {
+        let ids = &mut self.ids;
+        let defaults = &self.scopes.last().unwrap().node;
+        self.node_attributes.entry(id).or_insert_with_key(|id| {
+            ids.push(id.clone());
+            defaults.clone()
+        })
+    }
+
+    fn add_edge(&mut self, from: Cow<'a, str>, to: Cow<'a, str>, attributes: &Attributes<'a>) {
+        // undirected edges are the same in both directions
+        let key = if self.directed || from <= to {
+            (from.clone(), to.clone())
+        } else {
+            (to.clone(), from.clone())
+        };
+        if self.strict {
+            if let Some(&n) = self.seen_links.get(&key) {
+                self.link_attributes[n].extend(attributes.clone());
+                return;
+            }
+            self.seen_links.insert(key, self.links.len());
+        }
+        let mut edge_attributes = self.scope().edge.clone();
+        edge_attributes.extend(attributes.clone());
+        self.links.push((from, to));
+        self.link_attributes.push(edge_attributes);
+    }
+
+    /// Processes statements and returns all nodes mentioned in them.
+    fn statements(&mut self, statements: Pair<'a, Rule>) -> Vec<Cow<'a, str>> {
+        let mut nodes = vec![];
+        for statement in statements.into_inner() {
+            match statement.as_rule() {
+                Rule::link => nodes.extend(self.link(statement)),
+                Rule::node => nodes.push(self.node(statement)),
+                Rule::subgraph => nodes.extend(self.subgraph(statement, false)),
+                Rule::attribute => {
+                    let (name, value) = attribute(statement);
+                    self.scope().graph.insert(name, value);
+                }
+                Rule::attribute_statement => {
+                    let mut items = statement.into_inner();
+                    let target = items.next().unwrap().as_str().to_ascii_lowercase();
+                    let attributes = attributes(items.next().unwrap());
+                    let scope = self.scope();
+                    match target.as_str() {
+                        "graph" => scope.graph.extend(attributes),
+                        "node" => scope.node.extend(attributes),
+                        "edge" => scope.edge.extend(attributes),
+                        _ => unreachable!(),
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+        nodes
+    }
+
+    fn subgraph(&mut self, subgraph: Pair<'a, Rule>, edge_operand: bool) -> Vec<Cow<'a, str>> {
+        let outer = self.scope();
+        let scope = Scope {
+            node: outer.node.clone(),
+            edge: outer.edge.clone(),
+            ..Scope::default()
+        };
+        self.scopes.push(scope);
+        let mut name = None;
+        let mut nodes = vec![];
+        for item in subgraph.into_inner() {
+            match item.as_rule() {
+                Rule::id => name = Some(id(item)),
+                Rule::statements => nodes = self.statements(item),
+                _ => unreachable!(),
+            }
+        }
+        let scope = self.scopes.pop().unwrap();
+        let mut seen = HashSet::new();
+        nodes.retain(|id| seen.insert(id.clone()));
+
+        let siblings = &mut self.scope().subgraphs;
+        // subgraph with the same name continues the previous one
+        if let Some(same) = siblings
+            .iter_mut()
+            .find(|s| name.is_some() && s.name == name)
+        {
+            same.attributes.extend(scope.graph);
+            for id in &nodes {
+                if !same.nodes.contains(id) {
+                    same.nodes.push(id.clone());
+                }
+            }
+            same.subgraphs.extend(scope.subgraphs);
+        } else {
+            siblings.push(ParsedSubgraph {
+                name,
+                attributes: scope.graph,
+                nodes: nodes.clone(),
+                subgraphs: scope.subgraphs,
+                edge_operand,
+            });
+        }
+        nodes
+    }
+
+    fn node(&mut self, node: Pair<'a, Rule>) -> Cow<'a, str> {
+        let mut items = node.into_inner();
+        // a port in a node statement has no meaning and is ignored
+        let (id, _) = node_id(items.next().unwrap());
+        let attributes = items.next().map(attributes).unwrap_or_default();
+        self.add_node(id.clone()).extend(attributes);
+        id
+    }
+
+    /// Expands `a -> b -> c` chains and `{a b} -> c` groups into separate edges,
+    /// ports of `a:port` operands are stored as `tailport` and `headport`.
+    fn link(&mut self, link: Pair<'a, Rule>) -> Vec<Cow<'a, str>> {
+        let mut operands = vec![];
+        let mut attrs = Attributes::new();
+        for item in link.into_inner() {
+            match item.as_rule() {
+                Rule::edge_op => self.check_edge_op(&item),
+                Rule::node_id => {
+                    let (id, port) = node_id(item);
+                    self.add_node(id.clone());
+                    operands.push(vec![(id, port)]);
+                }
+                Rule::subgraph => {
+                    let nodes = self.subgraph(item, true);
+                    operands.push(nodes.into_iter().map(|id| (id, None)).collect());
+                }
+                Rule::attributes => attrs = attributes(item),
+                _ => unreachable!(),
+            }
+        }
+        for pair in operands.windows(2) {
+            for (from, from_port) in &pair[0] {
+                for (to, to_port) in &pair[1] {
+                    let mut attrs = attrs.clone();
+                    if let Some(port) = from_port {
+                        attrs.insert("tailport".into(), port.clone());
+                    }
+                    if let Some(port) = to_port {
+                        attrs.insert("headport".into(), port.clone());
+                    }
+                    self.add_edge(from.clone(), to.clone(), &attrs);
+                }
+            }
+        }
+        operands.into_iter().flatten().map(|(id, _)| id).collect()
+    }
+
+    fn check_edge_op(&mut self, op: &Pair<'a, Rule>) {
+        if (op.as_str() == "->") != self.directed {
+            let span = op.as_span();
+            self.errors.push(ParseError::edge_operator(
+                self.data,
+                span.start(),
+                span.end(),
+                self.directed,
+            ));
+        }
+    }
+
+    fn build(mut self, name: Option<Cow<'a, str>>) -> DotGraph<'a> {
+        let root = self.scopes.pop().unwrap();
+        let mut graph = DirectedGraph::new(&self.ids, &self.links);
+        let mut node_attributes = graph.node_map();
+        for (id, attributes) in node_attributes.iter_mut() {
+            let original_id = graph.original_id(id).unwrap();
+            *attributes = self.node_attributes.remove(original_id).unwrap_or_default();
+        }
+        let ids: HashMap<_, _> = graph
+            .iter_nodes_ids()
+            .map(|id| (graph.original_id(id).unwrap().clone(), id))
+            .collect();
+        let subgraphs = root
+            .subgraphs
+            .into_iter()
+            .map(|s| s.into_subgraph(&ids))
+            .collect();
+        // DirectedGraph::new keeps edges order but moves self edges aside.
+        let mut edge_attributes = graph.edge_map();
+        let mut self_edge_attributes = vec![];
+        let mut edge_ids = edge_attributes.iter_ids();
+        for ((from, to), attributes) in self.links.iter().zip(self.link_attributes) {
+            if from == to {
+                self_edge_attributes.push(attributes);
+            } else {
+                let edge_id = edge_ids.next().unwrap();
+                set_edge_params(graph.edge_mut(edge_id), &attributes);
+                edge_attributes.set(edge_id, attributes);
+            }
+        }
+        DotGraph {
+            graph,
+            name,
+            directed: self.directed,
+            strict: self.strict,
+            attributes: root.graph,
+            node_attributes,
+            edge_attributes,
+            self_edge_attributes,
+            subgraphs,
         }
     }
-    DotGraph {
-        graph: g,
-        labels: map,
+}
+
+/// Largest `minlen`, longer edges would take ages to split into virtual
+/// nodes.
+pub const MAX_MIN_LENGTH: u32 = 100;
+
+/// Applies `weight`, `minlen` and `constraint`, invalid values are ignored.
+/// `minlen` is clamped to [`MAX_MIN_LENGTH`], values which do not fit ranks
+/// are invalid.
+fn set_edge_params(edge: &mut Edge, attributes: &Attributes) {
+    if let Some(weight) = attributes
+        .get("weight")
+        .and_then(|v| v.parse().ok())
+        .filter(|&w| w >= 0)
+    {
+        edge.weight = weight;
+    }
+    if let Some(min_length) = attributes
+        .get("minlen")
+        .and_then(|v| v.parse::<u32>().ok())
+        .filter(|&l| l <= i32::MAX as u32)
+    {
+        edge.min_length = min_length.min(MAX_MIN_LENGTH);
+    }
+    if let Some(constraint) = attributes.get("constraint") {
+        edge.constraint = !matches!(constraint.to_lowercase().as_str(), "false" | "no" | "0");
     }
 }
 
-fn link<'a>(link: Pair<'a, Rule>) -> (&'a str, &'a str) {
-    let mut items = link.into_inner();
-    let from = items.next().unwrap().as_str();
-    let to = items.next().unwrap().as_str();
-    (from, to)
+fn attributes(attributes: Pair<'_, Rule>) -> Attributes<'_> {
+    attributes.into_inner().map(attribute).collect()
 }
 
-fn node<'a>(node: Pair<'a, Rule>) -> (&'a str, Option<&'a str>) {
-    let mut items = node.into_inner();
-    let name = items.next().unwrap().as_str();
-    let label = items.next().and_then(extract_label);
-    (name, label)
+fn attribute(attribute: Pair<'_, Rule>) -> (Cow<'_, str>, Cow<'_, str>) {
+    let mut items = attribute.into_inner();
+    let name = id(items.next().unwrap());
+    let value = id(items.next().unwrap());
+    (name, value)
 }
 
-fn extract_label<'a>(attributes: Pair<'a, Rule>) -> Option<&'a str> {
-    attributes.into_inner().skip(1).next().map(|s| s.as_str())
+/// Node id and optional `port:compass` of the node reference.
+fn node_id(node_id: Pair<'_, Rule>) -> (Cow<'_, str>, Option<Cow<'_, str>>) {
+    let mut items = node_id.into_inner();
+    let node = id(items.next().unwrap());
+    let port = items.next().map(|port| {
+        let mut parts = port.into_inner().map(id);
+        let name = parts.next().unwrap();
+        match parts.next() {
+            Some(compass) => Cow::Owned(format!("{name}:{compass}")),
+            None => name,
+        }
+    });
+    (node, port)
+}
+
+/// Value of the identifier, quoted strings are unescaped and concatenated.
+fn id(id: Pair<'_, Rule>) -> Cow<'_, str> {
+    let item = id.into_inner().next().unwrap();
+    if item.as_rule() != Rule::quoted {
+        return Cow::Borrowed(item.as_str());
+    }
+    let mut parts = item.into_inner().map(|part| unescape(part.as_str()));
+    let first = parts.next().unwrap();
+    parts.fold(first, |mut result, part| {
+        result.to_mut().push_str(&part);
+        result
+    })
+}
+
+/// Only `\"` is an escape in DOT strings, other backslash sequences are kept
+/// for labels, a backslash before a newline joins lines.
+fn unescape(data: &str) -> Cow<'_, str> {
+    if !data.contains('\\') {
+        return Cow::Borrowed(data);
+    }
+    let mut result = String::with_capacity(data.len());
+    let mut chars = data.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some('"')) => {
+                result.push('"');
+                chars.next();
+            }
+            ('\\', Some('\n')) => {
+                chars.next();
+            }
+            ('\\', Some('\r')) => {
+                chars.next();
+                chars.next_if_eq(&'\n');
+            }
+            ('\\', Some(&next)) => {
+                result.push(c);
+                result.push(next);
+                chars.next();
+            }
+            _ => result.push(c),
+        }
+    }
+    Cow::Owned(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digraph() {
+        let dot = parse("digraph x { a -> b; }").unwrap();
+        assert_eq!(dot.name, Some("x".into()));
+        assert!(dot.directed);
+        assert!(!dot.strict);
+        assert_eq!(dot.graph.edges_count(), 1);
+    }
+
+    #[test]
+    fn undirected_anonymous() {
+        let dot = parse("graph { a -- b; b -- c; }").unwrap();
+        assert_eq!(dot.name, None);
+        assert!(!dot.directed);
+        assert_eq!(dot.graph.nodes_count(), 3);
+        assert_eq!(dot.graph.edges_count(), 2);
+    }
+
+    #[test]
+    fn strict() {
+        let dot = parse("strict digraph { a -> b; a -> b; b -> a; }").unwrap();
+        assert!(dot.strict);
+        assert_eq!(dot.graph.edges_count(), 2);
+
+        let dot = parse("strict graph { a -- b; b -- a; a -- b; }").unwrap();
+        assert_eq!(dot.graph.edges_count(), 1);
+
+        let dot = parse("digraph { a -> b; a -> b; }").unwrap();
+        assert_eq!(dot.graph.edges_count(), 2);
+    }
+
+    #[test]
+    fn case_insensitive_keywords() {
+        let dot = parse("STRICT DiGraph G{ a -> b; }").unwrap();
+        assert!(dot.strict && dot.directed);
+        assert_eq!(dot.name, Some("G".into()));
+    }
+
+    #[test]
+    fn comments() {
+        let dot = parse(
+            "# 1 \"file.dot\"\n// leading\ndigraph /* name */ G { a -> /* inline */ b; // tail\n\
+             # preprocessor line\n c }",
+        )
+        .unwrap();
+        assert_eq!(dot.name, Some("G".into()));
+        assert_eq!(edges(&dot), &[("a", "b")]);
+        assert_eq!(dot.graph.nodes_count(), 3);
+        // `#` starts a comment only at the beginning of a line.
+        assert!(parse("digraph { a -> b # x\n }").is_err());
+        assert!(parse("digraph { a [label=x#y] }").is_err());
+    }
+
+    #[test]
+    fn identifiers() {
+        let dot = parse(
+            r#"digraph "my graph" { "std::io" -> "my node"; -1 -> .5 -> 2.75; узел -> _x1;
+                "a \"q\"" [label="multi" + "part" + " \l"]; "long\
+line" }"#,
+        )
+        .unwrap();
+        assert_eq!(dot.name, Some("my graph".into()));
+        assert_eq!(
+            edges(&dot),
+            &[
+                ("std::io", "my node"),
+                ("-1", ".5"),
+                (".5", "2.75"),
+                ("узел", "_x1")
+            ]
+        );
+        let id = dot
+            .graph
+            .iter_nodes_ids()
+            .find(|&id| dot.graph.original_id(id).unwrap() == "a \"q\"")
+            .unwrap();
+        assert_eq!(dot.label(id), Some("multipart \\l"));
+        assert!(dot
+            .graph
+            .iter_nodes_ids()
+            .any(|id| dot.graph.original_id(id).unwrap() == "longline"));
+        assert!(matches!(
+            dot.graph.original_id(NodeId::from(0u32)),
+            Some(Cow::Borrowed("std::io"))
+        ));
+    }
+
+    #[test]
+    fn edge_chain() {
+        let dot = parse("digraph { a -> b -> c; }").unwrap();
+        assert_eq!(edges(&dot), &[("a", "b"), ("b", "c")]);
+    }
+
+    #[test]
+    fn node_groups() {
+        let dot = parse("digraph { a -> {b c d}; {e; f} -> {g h} -> i; j -> {}; }").unwrap();
+        assert_eq!(
+            edges(&dot),
+            &[
+                ("a", "b"),
+                ("a", "c"),
+                ("a", "d"),
+                ("e", "g"),
+                ("e", "h"),
+                ("f", "g"),
+                ("f", "h"),
+                ("g", "i"),
+                ("h", "i"),
+            ]
+        );
+        assert_eq!(dot.graph.nodes_count(), 10);
+    }
+
+    #[test]
+    fn nodes_in_order_of_appearance() {
+        let dot = parse("digraph { a -> b; c; b; }").unwrap();
+        let ids: Vec<_> = dot
+            .graph
+            .iter_nodes_ids()
+            .map(|id| dot.graph.original_id(id).unwrap().as_ref())
+            .collect();
+        assert_eq!(ids, &["a", "b", "c"]);
+    }
+
+    #[test]
+    fn attribute_lists() {
+        let dot = parse(
+            "digraph { a [label=\"A\", color=red; width=1.5] [shape=box]; b [color=\"red\"]; \
+             a -> b [label=x, weight=-2]; b -> b [label=\"loop\"]; c []; }",
+        )
+        .unwrap();
+        let a = NodeId::from(0u32);
+        let b = NodeId::from(1u32);
+        assert_eq!(dot.label(a), Some("A"));
+        assert_eq!(dot.node_attributes.get(a).get("width"), Some(&"1.5".into()));
+        assert_eq!(dot.node_attributes.get(a).get("shape"), Some(&"box".into()));
+        assert_eq!(dot.node_attributes.get(a).len(), 4);
+        assert_eq!(dot.label(b), None);
+        assert_eq!(dot.node_attributes.get(b).get("color"), Some(&"red".into()));
+        let edge = dot.edge_attributes.get(EdgeId::from(0u32));
+        assert_eq!(edge.get("label"), Some(&"x".into()));
+        assert_eq!(edge.get("weight"), Some(&"-2".into()));
+        assert_eq!(dot.self_edge_attributes.len(), 1);
+        assert_eq!(
+            dot.self_edge_attributes[0].get("label"),
+            Some(&"loop".into())
+        );
+    }
+
+    #[test]
+    fn edge_params() {
+        let dot = parse(
+            "digraph { edge [weight=3]; a -> b; a -> c [minlen=2, weight=-1]; \
+             c -> b [constraint=false]; b -> a [constraint=true, weight=x] }",
+        )
+        .unwrap();
+        let edges: Vec<_> = dot
+            .graph
+            .iter_edges()
+            .map(|e| (e.weight, e.min_length, e.constraint))
+            .collect();
+        assert_eq!(
+            edges,
+            &[(3, 1, true), (1, 2, true), (3, 1, false), (1, 1, true)]
+        );
+
+        let dot = parse("digraph { a -> b [minlen=4294967295]; b -> c [minlen=200000] }").unwrap();
+        let lengths: Vec<_> = dot.graph.iter_edges().map(|e| e.min_length).collect();
+        assert_eq!(lengths, &[1, MAX_MIN_LENGTH]);
+    }
+
+    #[test]
+    fn ports() {
+        let dot =
+            parse("digraph { a:p1:n -> b : s [tailport=x]; a:\"f 0\" -> {c d}; e:w [label=E] }")
+                .unwrap();
+        assert_eq!(edges(&dot), &[("a", "b"), ("a", "c"), ("a", "d")]);
+        let ports: Vec<_> = dot
+            .edge_attributes
+            .iter()
+            .map(|(_, a)| (a.get("tailport"), a.get("headport")))
+            .collect();
+        assert_eq!(
+            ports,
+            &[
+                (Some(&"p1:n".into()), Some(&"s".into())),
+                (Some(&"f 0".into()), None),
+                (Some(&"f 0".into()), None)
+            ]
+        );
+        assert_eq!(dot.label(NodeId::from(4u32)), Some("E"));
+    }
+
+    #[test]
+    fn strict_merges_edge_attributes() {
+        let dot = parse("strict digraph { a -> b [color=red]; a -> b [label=x]; }").unwrap();
+        let edge = dot.edge_attributes.get(EdgeId::from(0u32));
+        assert_eq!(edge.len(), 2);
+    }
+
+    #[test]
+    fn default_attributes() {
+        let dot = parse(
+            "digraph {
+                rankdir=LR
+                graph [fontsize=10]
+                a
+                node [shape=box, color=red]
+                edge [color=blue]
+                b -> c [color=green]
+                {
+                    node [shape=circle]
+                    edge [style=dashed]
+                    rank=same
+                    d -> e
+                }
+                f -> g
+                NODE [color=black]
+                h [shape=ellipse]
+            }",
+        )
+        .unwrap();
+        assert_eq!(dot.attributes.get("rankdir"), Some(&"LR".into()));
+        assert_eq!(dot.attributes.get("fontsize"), Some(&"10".into()));
+        assert_eq!(dot.attributes.get("rank"), None);
+        let node = |name| {
+            let id = dot
+                .graph
+                .iter_nodes_ids()
+                .find(|&id| dot.graph.original_id(id).unwrap().as_ref() == name)
+                .unwrap();
+            dot.node_attributes.get(id)
+        };
+        assert!(node("a").is_empty());
+        assert_eq!(node("b").get("shape"), Some(&"box".into()));
+        assert_eq!(node("d").get("shape"), Some(&"circle".into()));
+        assert_eq!(node("d").get("color"), Some(&"red".into()));
+        assert_eq!(node("f").get("shape"), Some(&"box".into()));
+        assert_eq!(node("h").get("shape"), Some(&"ellipse".into()));
+        assert_eq!(node("h").get("color"), Some(&"black".into()));
+
+        let edge = |n: u32| dot.edge_attributes.get(EdgeId::from(n));
+        assert_eq!(edge(0).get("color"), Some(&"green".into()));
+        assert_eq!(edge(1).get("style"), Some(&"dashed".into()));
+        assert_eq!(edge(1).get("color"), Some(&"blue".into()));
+        assert_eq!(edge(2).get("style"), None);
+    }
+
+    #[test]
+    fn group_as_edge_operand() {
+        let dot = parse("digraph { a -> { b -> c } }").unwrap();
+        assert_eq!(edges(&dot), &[("b", "c"), ("a", "b"), ("a", "c")]);
+    }
+
+    #[test]
+    fn subgraphs() {
+        let dot = parse(
+            "digraph {
+                subgraph cluster_a {
+                    label=\"A\"
+                    a; b
+                    subgraph inner { rank=same; c -> d }
+                }
+                subgraph { e }
+                {rank=same; f g}
+                subgraph cluster_a { h }
+                i -> subgraph s { j }
+            }",
+        )
+        .unwrap();
+        let ids = |s: &Subgraph| {
+            s.nodes
+                .iter()
+                .map(|&id| dot.graph.original_id(id).unwrap().as_ref())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(dot.subgraphs.len(), 4);
+        let cluster = &dot.subgraphs[0];
+        assert!(cluster.is_cluster());
+        assert_eq!(cluster.name, Some("cluster_a".into()));
+        assert_eq!(cluster.attributes.get("label"), Some(&"A".into()));
+        assert_eq!(ids(cluster), &["a", "b", "c", "d", "h"]);
+        assert_eq!(cluster.subgraphs.len(), 1);
+        assert_eq!(cluster.subgraphs[0].name, Some("inner".into()));
+        assert!(!cluster.subgraphs[0].is_cluster());
+        assert_eq!(
+            cluster.subgraphs[0].attributes.get("rank"),
+            Some(&"same".into())
+        );
+        assert_eq!(ids(&cluster.subgraphs[0]), &["c", "d"]);
+        assert_eq!(dot.subgraphs[1].name, None);
+        assert_eq!(ids(&dot.subgraphs[1]), &["e"]);
+        assert_eq!(ids(&dot.subgraphs[2]), &["f", "g"]);
+        assert_eq!(dot.subgraphs[3].name, Some("s".into()));
+        assert_eq!(edges(&dot), &[("c", "d"), ("i", "j")]);
+    }
+
+    #[test]
+    fn wrong_edge_op() {
+        assert!(parse("digraph { a -- b; }").is_err());
+        assert!(parse("graph { a -> b; }").is_err());
+        assert!(parse("graph { a -- b -> c; }").is_err());
+
+        let Err(errors) = parse("graph {\n  a -> b;\n  c -> d }") else {
+            panic!("must fail");
+        };
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[1].to_string(),
+            "3:5: edge operator `->` is not allowed in graph\n  |   c -> d }\n  |     ^^"
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let Err(errors) = parse("digraph {\n  a -> ;\n  b -> c;\n  d [x=];\n}") else {
+            panic!("must fail");
+        };
+        assert_eq!(errors.len(), 2);
+        let span = errors[0].span();
+        assert_eq!((span.line, span.column, span.start), (2, 8, 17));
+        let ParseError::Syntax {
+            expected, snippet, ..
+        } = &errors[0]
+        else {
+            panic!("syntax error expected");
+        };
+        assert_eq!(expected, &["subgraph", "identifier"]);
+        assert_eq!(snippet, "  a -> ;");
+        assert_eq!(errors[1].span().line, 4);
+
+        let Err(errors) = parse("digraph { a -> b; ") else {
+            panic!("must fail");
+        };
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span().column, 19);
+    }
+
+    fn edges<'a>(dot: &'a DotGraph) -> Vec<(&'a str, &'a str)> {
+        dot.graph
+            .iter_edges()
+            .map(|e| {
+                (
+                    dot.graph.original_id(e.from).unwrap().as_ref(),
+                    dot.graph.original_id(e.to).unwrap().as_ref(),
+                )
+            })
+            .collect()
+    }
 }
@@ -0,0 +1,268 @@
+use crate::read_dot::Attributes;
+use crate::text;
+
+/// Field of a `record` or `Mrecord` label, `{a|<p1> b|c}` is a row of three
+/// text fields, braces flip the direction of nested fields.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Field {
+    Text { port: Option<String>, text: String },
+    Fields(Vec<Field>),
+}
+
+/// Text field placed inside of the node box, coordinates are fractions of the
+/// node size, (0, 0) is the top left corner.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldBox {
+    pub port: Option<String>,
+    pub text: String,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+pub fn is_record(attributes: &Attributes) -> bool {
+    attributes
+        .get("shape")
+        .is_some_and(|shape| shape == "record" || shape == "Mrecord")
+}
+
+/// Space between a field text and the field borders.
+pub const FIELD_MARGIN: (f64, f64) = (8., 4.);
+
+/// Fields of a record node measured with its font and the size of the whole
+/// record in points, None for other shapes and invalid labels.
+pub fn node_fields(
+    attributes: &Attributes,
+    label: &str,
+    horizontal: bool,
+) -> Option<(Vec<FieldBox>, (f64, f64))> {
+    if !is_record(attributes) {
+        return None;
+    }
+    let field = parse(label)?;
+    let (font, size) = text::font(attributes);
+    let measure = |text: &str| {
+        let (width, height) = text::text_size(text, font, size);
+        (width + FIELD_MARGIN.0 * 2., height + FIELD_MARGIN.1 * 2.)
+    };
+    Some((
+        field.layout(horizontal, &measure),
+        field.size(horizontal, &measure),
+    ))
+}
+
+/// Parses a record label, None if braces or `<>` are unbalanced.
+pub fn parse(label: &str) -> Option<Field> {
+    let mut chars = label.chars().peekable();
+    let fields = parse_fields(&mut chars)?;
+    match chars.next() {
+        None => Some(Field::Fields(fields)),
+        Some(_) => None,
+    }
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+fn parse_fields(chars: &mut Chars) -> Option<Vec<Field>> {
+    let mut fields = vec![];
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.next_if_eq(&'{').is_some() {
+            let nested = parse_fields(chars)?;
+            chars.next_if_eq(&'}')?;
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            fields.push(Field::Fields(nested));
+        } else {
+            fields.push(parse_text(chars)?);
+        }
+        if chars.next_if_eq(&'|').is_none() {
+            return Some(fields);
+        }
+    }
+}
+
+fn parse_text(chars: &mut Chars) -> Option<Field> {
+    let mut port = None;
+    let mut text = String::new();
+    while let Some(&c) = chars.peek() {
+        match c {
+            '|' | '{' | '}' => break,
+            '<' => {
+                chars.next();
+                let mut name = String::new();
+                loop {
+                    match chars.next()? {
+                        '>' => break,
+                        '\\' => name.push(chars.next()?),
+                        c => name.push(c),
+                    }
+                }
+                port = Some(name.trim().to_string());
+            }
+            '>' => return None,
+            '\\' => {
+                chars.next();
+                match chars.next() {
+                    Some(c @ ('{' | '}' | '|' | '<' | '>' | ' ')) => text.push(c),
+                    Some(c) => {
+                        text.push('\\');
+                        text.push(c);
+                    }
+                    None => text.push('\\'),
+                }
+            }
+            c => {
+                chars.next();
+                text.push(c);
+            }
+        }
+    }
+    Some(Field::Text {
+        port,
+        text: text.trim().to_string(),
+    })
+}
+
+impl Field {
+    /// Width and height of the text fields measured by `measure`.
+    fn size(&self, horizontal: bool, measure: &impl Fn(&str) -> (f64, f64)) -> (f64, f64) {
+        match self {
+            Field::Text { text, .. } => measure(text),
+            Field::Fields(fields) => {
+                let sizes = fields.iter().map(|f| f.size(!horizontal, measure));
+                if horizontal {
+                    sizes.fold((0., 0.), |a, s| (a.0 + s.0, f64::max(a.1, s.1)))
+                } else {
+                    sizes.fold((0., 0.), |a, s| (f64::max(a.0, s.0), a.1 + s.1))
+                }
+            }
+        }
+    }
+
+    /// Text fields with their boxes, the top level fields are placed from
+    /// left to right if `horizontal`, otherwise from top to bottom like in
+    /// Graphviz with `rankdir=LR`. Space is split in proportion to the text
+    /// size.
+    pub fn layout(&self, horizontal: bool, measure: &impl Fn(&str) -> (f64, f64)) -> Vec<FieldBox> {
+        let mut boxes = vec![];
+        self.place(horizontal, (0., 0., 1., 1.), measure, &mut boxes);
+        boxes
+    }
+
+    fn place(
+        &self,
+        horizontal: bool,
+        area: (f64, f64, f64, f64),
+        measure: &impl Fn(&str) -> (f64, f64),
+        boxes: &mut Vec<FieldBox>,
+    ) {
+        let (x, y, width, height) = area;
+        match self {
+            Field::Text { port, text } => boxes.push(FieldBox {
+                port: port.clone(),
+                text: text.clone(),
+                x,
+                y,
+                width,
+                height,
+            }),
+            Field::Fields(fields) => {
+                let sizes: Vec<_> = fields
+                    .iter()
+                    .map(|f| f.size(!horizontal, measure))
+                    .collect();
+                let total: f64 = sizes
+                    .iter()
+                    .map(|s| if horizontal { s.0 } else { s.1 })
+                    .sum();
+                let mut offset = 0.;
+                for (field, size) in fields.iter().zip(sizes) {
+                    let part = if horizontal { size.0 } else { size.1 } / total;
+                    let area = if horizontal {
+                        (x + offset * width, y, part * width, height)
+                    } else {
+                        (x, y + offset * height, width, part * height)
+                    };
+                    field.place(!horizontal, area, measure, boxes);
+                    offset += part;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Characters and lines.
+    fn chars(text: &str) -> (f64, f64) {
+        (text.chars().count().max(1) as f64, 1.)
+    }
+
+    fn text(port: Option<&str>, text: &str) -> Field {
+        Field::Text {
+            port: port.map(|p| p.to_string()),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(
+            parse("{a|<p1> b|c} | d\\|e"),
+            Some(Field::Fields(vec![
+                Field::Fields(vec![
+                    text(None, "a"),
+                    text(Some("p1"), "b"),
+                    text(None, "c")
+                ]),
+                text(None, "d|e"),
+            ]))
+        );
+        assert_eq!(
+            parse("<f0> |<f1>"),
+            Some(Field::Fields(vec![
+                text(Some("f0"), ""),
+                text(Some("f1"), "")
+            ]))
+        );
+        assert_eq!(parse("{a|b"), None);
+        assert_eq!(parse("a}"), None);
+        assert_eq!(parse("<a"), None);
+    }
+
+    #[test]
+    fn layout() {
+        let boxes = parse("<l> ab|{<t> c|<b> d}").unwrap().layout(true, &chars);
+        let rects: Vec<_> = boxes
+            .iter()
+            .map(|b| (b.port.as_deref().unwrap(), b.x, b.y, b.width, b.height))
+            .collect();
+        assert_eq!(
+            rects,
+            &[
+                ("l", 0., 0., 2. / 3., 1.),
+                ("t", 2. / 3., 0., 1. / 3., 0.5),
+                ("b", 2. / 3., 0.5, 1. / 3., 0.5)
+            ]
+        );
+        let boxes = parse("a|{b|c}").unwrap().layout(false, &chars);
+        let rects: Vec<_> = boxes
+            .iter()
+            .map(|b| (b.x, b.y, b.width, b.height))
+            .collect();
+        assert_eq!(
+            rects,
+            &[(0., 0., 1., 0.5), (0., 0.5, 0.5, 0.5), (0.5, 0.5, 0.5, 0.5)]
+        );
+
+        let attributes = [("shape".into(), "record".into())].into();
+        let (boxes, size) = node_fields(&attributes, "a|bb", true).unwrap();
+        // Times `a` and `bb` are 6.216 and 14 points wide with 14 points font.
+        assert_eq!(size, (6.216 + 14. + 32., 16.8 + 8.));
+        assert_eq!(boxes[1].x, (6.216 + 16.) / size.0);
+        assert_eq!(node_fields(&Attributes::new(), "a|bb", true), None);
+    }
+}
@@ -0,0 +1,137 @@
+//! Self loops, edges from a node to itself. In the top to bottom layout
+//! loops leave the node on the right side up and come back lower, loops of
+//! one node are nested: every next loop goes around the previous one and its
+//! label. The space they take is reserved by
+//! [`crate::xcoord::x_coordinates`].
+
+use crate::graph::*;
+use crate::label::{self, LABEL_GAP};
+use crate::layout::{Point, RankDir, Rect};
+use crate::read_dot::DotGraph;
+
+/// Distance between a node side and its first loop and between neighbor
+/// loops.
+pub const LOOP_STEP: f64 = 16.;
+
+/// Self loop in the top to bottom layout.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelfLoop {
+    pub node: NodeId,
+    /// Distance from the node side to the rightmost point of the loop.
+    pub offset: f64,
+    /// Index of the loop among `count` loops of the node.
+    pub index: usize,
+    pub count: usize,
+    /// Size of the `label`, turned to the top to bottom layout.
+    pub label: Option<(f64, f64)>,
+}
+
+/// Loops of [`DirectedGraph::iter_self_edges`] in the same order.
+pub fn self_loops(dot: &DotGraph, rankdir: RankDir) -> Vec<SelfLoop> {
+    let graph = &dot.graph;
+    let mut counts = NodeMap::new(graph.nodes_count());
+    let mut widths = NodeMap::new(graph.nodes_count());
+    let mut loops = vec![];
+    for (&node, attributes) in graph.iter_self_edges().zip(&dot.self_edge_attributes) {
+        let label = label::text_size(attributes, "label").map(|(width, height)| {
+            if rankdir.is_horizontal() {
+                (height, width)
+            } else {
+                (width, height)
+            }
+        });
+        let offset = *widths.get(node) + LOOP_STEP;
+        let width = offset + label.map_or(0., |(width, _)| LABEL_GAP + width);
+        widths.set(node, width);
+        loops.push(SelfLoop {
+            node,
+            offset,
+            index: *counts.get(node),
+            count: 0,
+            label,
+        });
+        counts.set(node, *counts.get(node) + 1);
+    }
+    for self_loop in &mut loops {
+        self_loop.count = *counts.get(self_loop.node);
+    }
+    loops
+}
+
+/// Space on the right of every node taken by its loops with labels.
+pub fn loop_widths(dot: &DotGraph, rankdir: RankDir) -> NodeMap<f64> {
+    let mut widths = NodeMap::new(dot.graph.nodes_count());
+    for self_loop in self_loops(dot, rankdir) {
+        let width = self_loop.offset + self_loop.label.map_or(0., |(width, _)| LABEL_GAP + width);
+        widths.set(self_loop.node, width);
+    }
+    widths
+}
+
+impl SelfLoop {
+    /// Two curves from the node center up right to the rightmost point and
+    /// back below the center, they are clipped by the node outline later.
+    /// Outer loops are higher, so the ends of nested loops are apart, but all
+    /// of them stay in the node height.
+    pub fn points(&self, node: &Rect) -> Vec<Point> {
+        let (x, y) = node.center();
+        let right = node.right() + self.offset;
+        // Curves go about 1.4 `dy` above and below the center.
+        let dy = node.height * (0.25 + 0.1 * self.index as f64 / self.count as f64);
+        let middle = (x + right) / 2.;
+        vec![
+            (x, y - dy),
+            (middle, y - dy * 2.),
+            (right, y - dy),
+            (right, y),
+            (right, y + dy),
+            (middle, y + dy * 2.),
+            (x, y + dy),
+        ]
+    }
+
+    /// Center of the label on the right of the loop.
+    pub fn label_center(&self, node: &Rect) -> Option<Point> {
+        let (width, _) = self.label?;
+        let x = node.right() + self.offset + LABEL_GAP + width / 2.;
+        Some((x, node.center().1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_dot::parse;
+
+    #[test]
+    fn nested() {
+        let dot = parse("digraph { a -> a; a -> b; a -> a [label=x]; a -> a; b -> b }").unwrap();
+        let loops = self_loops(&dot, RankDir::TopToBottom);
+        let (a, b) = (NodeId::from(0u32), NodeId::from(1u32));
+        assert_eq!(
+            loops
+                .iter()
+                .map(|l| (l.node, l.index, l.count))
+                .collect::<Vec<_>>(),
+            &[(a, 0, 3), (a, 1, 3), (a, 2, 3), (b, 0, 1)]
+        );
+        let (width, _) = loops[1].label.unwrap();
+        // The third loop goes around the label of the second one.
+        assert_eq!(loops[2].offset, LOOP_STEP * 3. + LABEL_GAP + width);
+        let widths = loop_widths(&dot, RankDir::TopToBottom);
+        assert_eq!(*widths.get(a), loops[2].offset);
+        assert_eq!(*widths.get(b), LOOP_STEP);
+
+        let node = Rect {
+            x: 0.,
+            y: 0.,
+            width: 40.,
+            height: 36.,
+        };
+        let points = loops[1].points(&node);
+        assert_eq!(points.len(), 7);
+        assert_eq!(points[3], (40. + LOOP_STEP * 2., 18.));
+        assert_eq!(loops[1].label_center(&node).unwrap().1, 18.);
+        assert_eq!(loops[0].label_center(&node), None);
+    }
+}
@@ -0,0 +1,229 @@
+use std::f64::consts::{PI, SQRT_2};
+
+use crate::layout::Point;
+use crate::read_dot::Attributes;
+
+/// Space between the two circles of `doublecircle`.
+const DOUBLE_GAP: f64 = 4.;
+/// Default size of `point` nodes, 0.05 inches.
+pub const POINT_SIZE: f64 = 3.6;
+
+/// Outline of a node, the Graphviz `shape` attribute. Records are boxes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    Ellipse,
+    Circle,
+    DoubleCircle,
+    Box,
+    Point,
+    /// `plaintext`, `plain` and `none`: only the label, `plain` has no margin.
+    PlainText {
+        margin: bool,
+    },
+    Note,
+    Folder,
+    Cylinder,
+    /// Regular polygon with a flat bottom, `orientation` rotates it clockwise
+    /// in degrees, `skew` shifts the top to the right and the bottom to the
+    /// left, positive `distortion` makes the top wider than the bottom.
+    Polygon {
+        sides: u32,
+        orientation: f64,
+        skew: f64,
+        distortion: f64,
+    },
+}
+
+impl Shape {
+    /// Shape of a node from its `shape` attribute and for polygons `sides`,
+    /// `orientation`, `skew` and `distortion`. Unknown shapes are ellipses.
+    pub fn from_attributes(attributes: &Attributes) -> Self {
+        let polygon = |sides, orientation, skew, distortion| Shape::Polygon {
+            sides,
+            orientation,
+            skew,
+            distortion,
+        };
+        let number = |name: &str, default: f64| {
+            attributes
+                .get(name)
+                .and_then(|v| v.trim().parse::<f64>().ok())
+                .filter(|v| v.is_finite())
+                .unwrap_or(default)
+        };
+        match attributes.get("shape").map_or("ellipse", |s| s.as_ref()) {
+            "circle" => Shape::Circle,
+            "doublecircle" => Shape::DoubleCircle,
+            "box" | "rect" | "rectangle" | "square" | "record" | "Mrecord" => Shape::Box,
+            "point" => Shape::Point,
+            "plaintext" | "none" => Shape::PlainText { margin: true },
+            "plain" => Shape::PlainText { margin: false },
+            "note" => Shape::Note,
+            "folder" => Shape::Folder,
+            "cylinder" => Shape::Cylinder,
+            "diamond" => polygon(4, 45., 0., 0.),
+            "triangle" => polygon(3, 0., 0., 0.),
+            "invtriangle" => polygon(3, 180., 0., 0.),
+            "pentagon" => polygon(5, 0., 0., 0.),
+            "hexagon" => polygon(6, 0., 0., 0.),
+            "septagon" => polygon(7, 0., 0., 0.),
+            "octagon" => polygon(8, 0., 0., 0.),
+            "parallelogram" => polygon(4, 0., 0.6, 0.),
+            "trapezium" => polygon(4, 0., 0., -0.4),
+            "polygon" => polygon(
+                number("sides", 4.).clamp(3., 100.) as u32,
+                number("orientation", 0.),
+                number("skew", 0.),
+                number("distortion", 0.),
+            ),
+            _ => Shape::Ellipse,
+        }
+    }
+
+    /// Node size around a label of the given size with margins.
+    pub fn size(self, (width, height): (f64, f64), margin: (f64, f64)) -> (f64, f64) {
+        let padded = (width + margin.0 * 2., height + margin.1 * 2.);
+        match self {
+            Shape::Point => (POINT_SIZE, POINT_SIZE),
+            Shape::PlainText { margin: false } => (width, height),
+            Shape::Box | Shape::PlainText { .. } | Shape::Note | Shape::Folder => padded,
+            Shape::Cylinder => (padded.0, padded.1 * 1.5),
+            Shape::Ellipse | Shape::Circle | Shape::Polygon { .. } => {
+                (padded.0 * SQRT_2, padded.1 * SQRT_2)
+            }
+            Shape::DoubleCircle => (
+                padded.0 * SQRT_2 + DOUBLE_GAP * 2.,
+                padded.1 * SQRT_2 + DOUBLE_GAP * 2.,
+            ),
+        }
+    }
+
+    /// Whether the width and height are equal, for circles, `square` and
+    /// `regular=true`.
+    pub fn is_square(self, attributes: &Attributes) -> bool {
+        matches!(self, Shape::Circle | Shape::DoubleCircle | Shape::Point)
+            || attributes.get("shape").is_some_and(|s| s == "square")
+            || attributes.get("regular").is_some_and(|v| v == "true")
+    }
+
+    /// Whether labels are drawn, `point` nodes have none.
+    pub fn has_label(self) -> bool {
+        self != Shape::Point
+    }
+
+    /// Vertices of polygonal shapes in a box of the given size with the top
+    /// left corner at the origin, None for round ones.
+    pub fn polygon(self, (width, height): (f64, f64)) -> Option<Vec<Point>> {
+        let (sides, orientation, skew, distortion) = match self {
+            Shape::Polygon {
+                sides,
+                orientation,
+                skew,
+                distortion,
+            } => (sides, orientation, skew, distortion),
+            Shape::Box
+            | Shape::PlainText { .. }
+            | Shape::Note
+            | Shape::Folder
+            | Shape::Cylinder => (4, 0., 0., 0.),
+            Shape::Ellipse | Shape::Circle | Shape::DoubleCircle | Shape::Point => return None,
+        };
+        // Vertices on the unit circle, y goes down, the first one is on the
+        // bottom left so the bottom side is flat.
+        let mut vertices: Vec<Point> = (0..sides)
+            .map(|n| {
+                let angle =
+                    PI / 2. + (2 * n + 1) as f64 * PI / sides as f64 + orientation.to_radians();
+                let (x, y) = (angle.cos(), angle.sin());
+                (x * (1. - distortion * y) - skew * y, y)
+            })
+            .collect();
+        // Stretches vertices to the node box.
+        let extent = |f: fn(&Point) -> f64| {
+            let min = vertices.iter().map(f).fold(f64::MAX, f64::min);
+            let max = vertices.iter().map(f).fold(f64::MIN, f64::max);
+            (min, max - min)
+        };
+        let ((x_min, x_size), (y_min, y_size)) = (extent(|p| p.0), extent(|p| p.1));
+        for (x, y) in &mut vertices {
+            *x = (*x - x_min) / x_size * width;
+            *y = (*y - y_min) / y_size * height;
+        }
+        Some(vertices)
+    }
+
+    /// Whether a point relative to the node center is inside of the node of
+    /// the given size.
+    pub fn contains(self, size: (f64, f64), (x, y): Point) -> bool {
+        match self.polygon(size) {
+            Some(vertices) => {
+                let (x, y) = (x + size.0 / 2., y + size.1 / 2.);
+                // Crossings of a ray going to the right.
+                let mut inside = false;
+                for (n, &(x1, y1)) in vertices.iter().enumerate() {
+                    let (x2, y2) = vertices[(n + 1) % vertices.len()];
+                    if (y1 > y) != (y2 > y) && x < x1 + (y - y1) / (y2 - y1) * (x2 - x1) {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+            None => {
+                let (a, b) = (size.0 / 2., size.1 / 2.);
+                (x / a).powi(2) + (y / b).powi(2) <= 1.
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(attributes: &[(&str, &str)]) -> Shape {
+        let attributes: Attributes = attributes
+            .iter()
+            .map(|&(n, v)| (n.into(), v.into()))
+            .collect();
+        Shape::from_attributes(&attributes)
+    }
+
+    #[test]
+    fn polygons() {
+        let size = (40., 20.);
+        let square = shape(&[("shape", "box")]).polygon(size).unwrap();
+        assert_eq!(square.len(), 4);
+        for (x, y) in square {
+            assert!((x.abs() < 1e-9 || (x - 40.).abs() < 1e-9));
+            assert!((y.abs() < 1e-9 || (y - 20.).abs() < 1e-9));
+        }
+        let diamond = shape(&[("shape", "diamond")]);
+        assert!(diamond.contains(size, (19., 0.)));
+        assert!(!diamond.contains(size, (15., 8.)));
+        assert!(shape(&[("shape", "box")]).contains(size, (19., 9.)));
+
+        let triangle = shape(&[("shape", "polygon"), ("sides", "3")]);
+        let vertices = triangle.polygon(size).unwrap();
+        assert_eq!(vertices.len(), 3);
+        assert!(vertices
+            .iter()
+            .any(|&(x, y)| (x - 20.).abs() < 1e-9 && y.abs() < 1e-9));
+        assert!(!triangle.contains(size, (-15., -8.)));
+
+        // The top of the parallelogram is shifted to the right.
+        let parallelogram = shape(&[("shape", "parallelogram")]);
+        assert!(parallelogram.contains(size, (19., -9.)));
+        assert!(!parallelogram.contains(size, (19., 9.)));
+    }
+
+    #[test]
+    fn sizes() {
+        let label = (10., 16.8);
+        let margin = (8., 4.);
+        assert_eq!(shape(&[("shape", "box")]).size(label, margin), (26., 24.8));
+        assert_eq!(shape(&[("shape", "plain")]).size(label, margin), label);
+        assert_eq!(shape(&[("shape", "point")]).size(label, margin), (3.6, 3.6));
+        assert!(!shape(&[]).contains((40., 20.), (15., 8.)));
+        assert!(shape(&[]).contains((40., 20.), (19., 0.)));
+    }
+}
@@ -0,0 +1,275 @@
+//! Edge routing from "A Technique for Drawing Directed Graphs" (Gansner et
+//! al. 1993), section 5: the edge goes through a corridor of boxes from the
+//! tail to the head, the shortest path through the corridor is approximated
+//! by Bezier curves which stay inside of it.
+
+use crate::layout::{Point, Rect};
+
+/// Allowed distance of curve points outside of the boxes.
+const TOLERANCE: f64 = 0.01;
+/// Points checked on every curve.
+const SAMPLES: u32 = 16;
+
+/// Control points of a smooth piecewise cubic Bezier curve from `start` to
+/// `end` inside of `boxes`, `3n + 1` of them. Boxes go from top to bottom,
+/// every box touches the next one, `start` is in the first box and `end` in
+/// the last one. The curve starts and ends vertically.
+pub fn route(start: Point, end: Point, boxes: &[Rect]) -> Vec<Point> {
+    let path = shortest_path(start, end, boxes);
+    let mut points = vec![start];
+    fit(&path, (0., 1.), (0., 1.), boxes, &mut points);
+    points
+}
+
+/// Horizontal segments shared by neighbor boxes as `(y, left, right)`, empty
+/// boxes are skipped.
+fn portals(boxes: &[Rect]) -> Vec<(f64, f64, f64)> {
+    let mut portals: Vec<(f64, f64, f64)> = vec![];
+    for pair in boxes.windows(2) {
+        let (y, left, right) = (
+            pair[0].bottom(),
+            pair[0].x.max(pair[1].x),
+            pair[0].right().min(pair[1].right()),
+        );
+        match portals.last_mut() {
+            // Portals of boxes without height are on the same line.
+            Some(last) if last.0 >= y => *last = (last.0, last.1.max(left), last.2.min(right)),
+            _ => portals.push((y, left, right)),
+        }
+    }
+    for portal in &mut portals {
+        if portal.1 > portal.2 {
+            let middle = (portal.1 + portal.2) / 2.;
+            (portal.1, portal.2) = (middle, middle);
+        }
+    }
+    portals
+}
+
+/// Shortest polyline from `start` to `end` through the boxes, the funnel
+/// algorithm: the path goes straight while the next portals are visible
+/// through the previous ones and bends at the portal end which blocks the
+/// view.
+pub fn shortest_path(start: Point, end: Point, boxes: &[Rect]) -> Vec<Point> {
+    let mut portals: Vec<_> = portals(boxes)
+        .iter()
+        .copied()
+        .filter(|p| p.0 > start.1 && p.0 < end.1)
+        .collect();
+    portals.push((end.1, end.0, end.0));
+
+    let mut path = vec![start];
+    let mut next = 0;
+    'apex: loop {
+        let apex = path[path.len() - 1];
+        // The narrowest slopes seen so far with their points and portals.
+        let (mut low, mut high) = ((f64::MIN, apex, 0), (f64::MAX, apex, 0));
+        for (index, &(y, left, right)) in portals.iter().enumerate().skip(next) {
+            let slope = |x: f64| (x - apex.0) / (y - apex.1);
+            let (left_slope, right_slope) = (slope(left), slope(right));
+            let corner = if left_slope > high.0 {
+                Some(high)
+            } else if right_slope < low.0 {
+                Some(low)
+            } else {
+                None
+            };
+            if let Some((_, point, index)) = corner {
+                path.push(point);
+                next = index + 1;
+                continue 'apex;
+            }
+            if left_slope > low.0 {
+                low = (left_slope, (left, y), index);
+            }
+            if right_slope < high.0 {
+                high = (right_slope, (right, y), index);
+            }
+        }
+        break;
+    }
+    path.push(end);
+    path
+}
+
+/// Appends curves from `path[0]` through the path with the given end
+/// directions to `points`. A curve is fitted by least squares and made
+/// flatter until it stays in the boxes, otherwise the path is split in the
+/// middle.
+fn fit(path: &[Point], start: Point, end: Point, boxes: &[Rect], points: &mut Vec<Point>) {
+    let (first, last) = (path[0], path[path.len() - 1]);
+    let (a, b) = tangent_lengths(path, start, end);
+    for factor in [1., 0.5, 0.25, 0.] {
+        let curve = [
+            first,
+            (
+                first.0 + start.0 * a * factor,
+                first.1 + start.1 * a * factor,
+            ),
+            (last.0 - end.0 * b * factor, last.1 - end.1 * b * factor),
+            last,
+        ];
+        // A straight line between neighbor path points is always inside.
+        if (factor == 0. && path.len() == 2) || inside(&curve, boxes) {
+            points.extend_from_slice(&curve[1..]);
+            return;
+        }
+    }
+    let middle = path.len() / 2;
+    let (before, after) = (path[middle - 1], path[middle + 1]);
+    let direction = normalize((after.0 - before.0, after.1 - before.1));
+    fit(&path[..=middle], start, direction, boxes, points);
+    fit(&path[middle..], direction, end, boxes, points);
+}
+
+/// Distances from the ends to the control points which approximate the path
+/// best, from "An Algorithm for Automatically Fitting Digitized Curves"
+/// (Schneider 1990).
+fn tangent_lengths(path: &[Point], start: Point, end: Point) -> (f64, f64) {
+    let (first, last) = (path[0], path[path.len() - 1]);
+    let distance = (last.0 - first.0).hypot(last.1 - first.1);
+    let mut lengths = vec![0.];
+    for pair in path.windows(2) {
+        let length = (pair[1].0 - pair[0].0).hypot(pair[1].1 - pair[0].1);
+        lengths.push(lengths[lengths.len() - 1] + length);
+    }
+    let total = lengths[lengths.len() - 1];
+    let dot = |a: Point, b: Point| a.0 * b.0 + a.1 * b.1;
+    let (mut c, mut x) = ([[0.; 2]; 2], [0.; 2]);
+    for (point, length) in path.iter().zip(&lengths) {
+        let u = if total > 0. { length / total } else { 0. };
+        let basis = [
+            (1. - u).powi(3),
+            3. * u * (1. - u).powi(2),
+            3. * u * u * (1. - u),
+            u.powi(3),
+        ];
+        let a = [
+            (start.0 * basis[1], start.1 * basis[1]),
+            (-end.0 * basis[2], -end.1 * basis[2]),
+        ];
+        c[0][0] += dot(a[0], a[0]);
+        c[0][1] += dot(a[0], a[1]);
+        c[1][1] += dot(a[1], a[1]);
+        let rest = (
+            point.0 - first.0 * (basis[0] + basis[1]) - last.0 * (basis[2] + basis[3]),
+            point.1 - first.1 * (basis[0] + basis[1]) - last.1 * (basis[2] + basis[3]),
+        );
+        x[0] += dot(a[0], rest);
+        x[1] += dot(a[1], rest);
+    }
+    let determinant = c[0][0] * c[1][1] - c[0][1] * c[0][1];
+    let (a, b) = if determinant.abs() > 1e-12 {
+        (
+            (x[0] * c[1][1] - x[1] * c[0][1]) / determinant,
+            (c[0][0] * x[1] - c[0][1] * x[0]) / determinant,
+        )
+    } else {
+        (0., 0.)
+    };
+    // Too short or negative tangents make loops, a third of the distance is
+    // the usual fallback.
+    let epsilon = distance * 1e-3;
+    if a < epsilon || b < epsilon {
+        (distance / 3., distance / 3.)
+    } else {
+        (a, b)
+    }
+}
+
+fn normalize((x, y): Point) -> Point {
+    let length = x.hypot(y);
+    if length > 0. {
+        (x / length, y / length)
+    } else {
+        (0., 1.)
+    }
+}
+
+fn inside(curve: &[Point; 4], boxes: &[Rect]) -> bool {
+    (0..=SAMPLES).all(|n| {
+        let (x, y) = bezier(curve, n as f64 / SAMPLES as f64);
+        boxes.iter().any(|b| {
+            x >= b.x - TOLERANCE
+                && x <= b.right() + TOLERANCE
+                && y >= b.y - TOLERANCE
+                && y <= b.bottom() + TOLERANCE
+        })
+    })
+}
+
+/// Point of the cubic Bezier curve at `t`.
+pub fn bezier(curve: &[Point; 4], t: f64) -> Point {
+    let s = 1. - t;
+    let weights = [s * s * s, 3. * s * s * t, 3. * s * t * t, t * t * t];
+    curve
+        .iter()
+        .zip(weights)
+        .fold((0., 0.), |(x, y), (p, w)| (x + p.0 * w, y + p.1 * w))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f64, y: f64, right: f64, bottom: f64) -> Rect {
+        Rect {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        }
+    }
+
+    #[test]
+    fn path_around_corners() {
+        // A corridor going right through a narrow box and back.
+        let boxes = [
+            rect(0., 0., 10., 10.),
+            rect(0., 10., 100., 20.),
+            rect(80., 20., 100., 30.),
+            rect(0., 30., 100., 40.),
+            rect(0., 40., 10., 50.),
+        ];
+        let path = shortest_path((5., 0.), (5., 50.), &boxes);
+        assert_eq!(
+            path,
+            &[
+                (5., 0.),
+                (10., 10.),
+                (80., 20.),
+                (80., 30.),
+                (10., 40.),
+                (5., 50.)
+            ]
+        );
+
+        let points = route((5., 0.), (5., 50.), &boxes);
+        assert_eq!(points.len() % 3, 1);
+        assert_eq!((points[0], points[points.len() - 1]), ((5., 0.), (5., 50.)));
+        for curve in points.windows(4).step_by(3) {
+            assert!(inside(&[curve[0], curve[1], curve[2], curve[3]], &boxes));
+        }
+    }
+
+    #[test]
+    fn straight() {
+        let boxes = [rect(0., 0., 10., 10.), rect(0., 10., 100., 20.)];
+        let points = route((5., 0.), (8., 20.), &boxes);
+        // A single curve starting and ending vertically.
+        assert_eq!(points.len(), 4);
+        assert_eq!(points[1].0, 5.);
+        assert_eq!(points[2].0, 8.);
+        // The path bends at the corner of the first box.
+        let points = route((5., 0.), (50., 20.), &boxes);
+        assert_eq!(points.len(), 7);
+        assert_eq!(points[3], (10., 10.));
+        // Zero height boxes are merged.
+        let boxes = [
+            rect(0., 0., 10., 10.),
+            rect(5., 10., 8., 10.),
+            rect(0., 10., 10., 20.),
+        ];
+        assert_eq!(portals(&boxes), &[(10., 5., 8.)]);
+    }
+}
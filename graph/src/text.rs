@@ -0,0 +1,151 @@
+use crate::read_dot::Attributes;
+
+/// Graphviz default `fontsize` in points.
+pub const DEFAULT_FONT_SIZE: f64 = 14.;
+/// Distance between baselines of label lines in font sizes.
+pub const LINE_HEIGHT: f64 = 1.2;
+
+/// Fonts with built-in metrics, other font names are measured with the
+/// closest one, so sizes do not depend on installed fonts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Font {
+    Times,
+    Helvetica,
+    Courier,
+}
+
+impl Font {
+    pub fn from_name(name: &str) -> Self {
+        let name = name.to_ascii_lowercase();
+        if ["courier", "mono", "consol"]
+            .iter()
+            .any(|n| name.contains(n))
+        {
+            Font::Courier
+        } else if ["helvetica", "arial", "sans", "verdana"]
+            .iter()
+            .any(|n| name.contains(n))
+        {
+            Font::Helvetica
+        } else {
+            Font::Times
+        }
+    }
+
+    /// Advance width in thousandths of the font size, from the Adobe font
+    /// metrics for printable ASCII. Other characters get the width of `n`,
+    /// East Asian ones are square.
+    fn char_width(self, c: char) -> u32 {
+        let table = match self {
+            Font::Times => &TIMES,
+            Font::Helvetica => &HELVETICA,
+            Font::Courier => return if is_wide(c) { 1000 } else { 600 },
+        };
+        match c {
+            ' '..='~' => table[c as usize - ' ' as usize],
+            c if is_wide(c) => 1000,
+            _ => table['n' as usize - ' ' as usize],
+        }
+    }
+}
+
+fn is_wide(c: char) -> bool {
+    matches!(c as u32, 0x1100..=0x115F | 0x2E80..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF | 0xFF00..=0xFF60)
+}
+
+/// Font and size of a node or cluster label.
+pub fn font(attributes: &Attributes) -> (Font, f64) {
+    let font = attributes
+        .get("fontname")
+        .map_or(Font::Times, |name| Font::from_name(name));
+    let size = attributes
+        .get("fontsize")
+        .and_then(|size| size.trim().parse::<f64>().ok())
+        .filter(|size| size.is_finite() && *size > 0.)
+        .unwrap_or(DEFAULT_FONT_SIZE);
+    (font, size)
+}
+
+pub fn text_width(text: &str, font: Font, size: f64) -> f64 {
+    text.chars().map(|c| font.char_width(c) as f64).sum::<f64>() * size / 1000.
+}
+
+/// Lines of a label split by new lines and the Graphviz `\n`, `\l` and `\r`
+/// escapes.
+pub fn lines(label: &str) -> Vec<&str> {
+    let mut lines = vec![];
+    let mut start = 0;
+    let mut chars = label.char_indices().peekable();
+    while let Some((n, c)) = chars.next() {
+        if c == '\n' {
+            lines.push(&label[start..n]);
+            start = n + 1;
+        } else if c == '\\' {
+            if let Some((_, 'n' | 'l' | 'r')) = chars.peek() {
+                lines.push(&label[start..n]);
+                start = n + 2;
+            }
+            chars.next();
+        }
+    }
+    if start < label.len() || lines.is_empty() {
+        lines.push(&label[start..]);
+    }
+    lines
+}
+
+/// Width of the longest line and height of all lines of the label.
+pub fn text_size(label: &str, font: Font, size: f64) -> (f64, f64) {
+    let lines = lines(label);
+    let width = lines
+        .iter()
+        .map(|line| text_width(line, font, size))
+        .fold(0., f64::max);
+    (width, lines.len() as f64 * size * LINE_HEIGHT)
+}
+
+#[rustfmt::skip]
+const TIMES: [u32; 95] = [
+    250, 333, 408, 500, 500, 833, 778, 180, 333, 333, 500, 564, 250, 333, 250, 278,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 278, 278, 564, 564, 564, 444,
+    921, 722, 667, 667, 722, 611, 556, 722, 722, 333, 389, 722, 611, 889, 722, 722,
+    556, 722, 667, 556, 611, 722, 722, 944, 722, 722, 611, 333, 278, 333, 469, 500,
+    333, 444, 500, 444, 500, 444, 333, 500, 500, 278, 278, 500, 278, 778, 500, 500,
+    500, 500, 333, 389, 278, 500, 500, 722, 500, 500, 444, 480, 200, 480, 541,
+];
+
+#[rustfmt::skip]
+const HELVETICA: [u32; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widths() {
+        assert_eq!(text_width("Hello", Font::Times, 10.), 22.22);
+        assert_eq!(text_width("Hello", Font::Helvetica, 10.), 22.78);
+        assert_eq!(text_width("Hello", Font::Courier, 10.), 30.);
+        assert_eq!(text_width("né", Font::Times, 10.), 10.);
+        assert_eq!(text_width("図", Font::Helvetica, 10.), 10.);
+        assert_eq!(Font::from_name("Arial Bold"), Font::Helvetica);
+        assert_eq!(Font::from_name("DejaVu Sans Mono"), Font::Courier);
+        assert_eq!(Font::from_name("serif"), Font::Times);
+    }
+
+    #[test]
+    fn label_lines() {
+        assert_eq!(lines("a\\nbc\\l"), &["a", "bc"]);
+        assert_eq!(lines("a\nb\\\\n"), &["a", "b\\\\n"]);
+        assert_eq!(lines(""), &[""]);
+        let (width, height) = text_size("ab\\nabc", Font::Courier, 10.);
+        assert_eq!((width, height), (18., 24.));
+    }
+}
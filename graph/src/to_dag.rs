@@ -37,15 +37,15 @@ pub fn to_dag<T: Debug>(graph: &mut DirectedGraph<T>) {
         stack.extend(
             graph
                 .iter_nodes_with_id()
-                .filter_map(|(id, n)| n.inputs.is_empty().then(|| Action::EnterToRoot(id))),
+                .filter_map(|(id, n)| n.inputs.is_empty().then_some(Action::EnterToRoot(id))),
         );
     }
 
     while visited_count != graph.nodes_count() {
         if stack.is_empty() {
-            // converts first unvisited node to the root by reverting all input edges
+            // converts an unvisited node on a cycle to the root by reverting all input edges
             // TODO: need to rework, maybe we don't need roots or can use rank to find it.
-            let first_unvisited = visited.find_first(|v| !v).unwrap();
+            let first_unvisited = on_cycle(graph, visited.find_first(|v| !v).unwrap());
             stack.push(Action::EnterToRoot(first_unvisited));
             let first_node = graph.node_mut(first_unvisited);
             let mut old_inputs = vec![];
@@ -118,6 +118,19 @@ pub fn to_dag<T: Debug>(graph: &mut DirectedGraph<T>) {
     }
 }
 
+/// Walks back from the unvisited node by inputs until a node repeats, the
+/// node is on a cycle. Every unvisited node has an input from an unvisited
+/// node, otherwise it would be a root or visited, so the walk stays in them.
+fn on_cycle<T: Debug>(graph: &DirectedGraph<T>, start: NodeId) -> NodeId {
+    let mut seen = graph.node_map::<bool>();
+    let mut id = start;
+    while !*seen.get(id) {
+        seen.set(id, true);
+        id = graph.edge(graph.node(id).inputs[0]).from;
+    }
+    id
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -185,4 +198,13 @@ mod tests {
             ],
         );
     }
+
+    #[test]
+    fn only_cycle_inverted() {
+        // `a` has no outputs, so its input is not on a cycle.
+        let mut dag = DirectedGraph::new(&['a', 'b', 'c'], &[('b', 'a'), ('b', 'c'), ('c', 'b')]);
+        to_dag(&mut dag);
+        let inverted: Vec<_> = dag.iter_edges().map(|e| e.is_inverted()).collect();
+        assert_eq!(inverted, &[false, false, true]);
+    }
 }
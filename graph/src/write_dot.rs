@@ -0,0 +1,370 @@
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+use crate::graph::*;
+use crate::layout::{EdgeLayout, Layout, Point};
+use crate::read_dot::{Attributes, DotGraph, Subgraph};
+
+/// Attributes of the graph elements with layout attributes added, in points
+/// with the origin at the bottom left corner like in Graphviz output.
+pub(crate) struct OutputAttributes<'d, 'a> {
+    dot: &'d DotGraph<'a>,
+    layout: Option<&'d Layout>,
+}
+
+impl<'d, 'a> OutputAttributes<'d, 'a> {
+    pub fn new(dot: &'d DotGraph<'a>, layout: Option<&'d Layout>) -> Self {
+        Self { dot, layout }
+    }
+
+    /// Graphviz y axis goes up.
+    pub fn point(&self, (x, y): Point) -> String {
+        let height = self.layout.map_or(0., |l| l.bounding_box.bottom());
+        format!("{},{}", number(x), number(height - y))
+    }
+
+    pub fn graph(&self) -> Attributes<'a> {
+        let mut attributes = self.dot.attributes.clone();
+        if let Some(layout) = self.layout {
+            let b = layout.bounding_box;
+            let bb = format!(
+                "{},{}",
+                self.point((b.x, b.bottom())),
+                self.point((b.right(), b.y))
+            );
+            attributes.insert("bb".into(), bb.into());
+        }
+        attributes
+    }
+
+    pub fn node(&self, id: NodeId) -> Attributes<'a> {
+        let mut attributes = self.dot.node_attributes.get(id).clone();
+        if let Some(layout) = self.layout {
+            let rect = layout.nodes.get(id);
+            attributes.insert("pos".into(), self.point(rect.center()).into());
+            attributes.insert("width".into(), number(rect.width / 72.).into());
+            attributes.insert("height".into(), number(rect.height / 72.).into());
+        }
+        attributes
+    }
+
+    /// `cluster` is the index in [`crate::cluster::Clusters`], they are
+    /// numbered in the order of a depth first walk over the subgraph tree.
+    pub fn subgraph(&self, subgraph: &Subgraph<'a>, cluster: Option<usize>) -> Attributes<'a> {
+        let mut attributes = subgraph.attributes.clone();
+        let boxes = self.layout.map(|l| &l.clusters);
+        if let Some(Some(b)) = cluster.and_then(|n| boxes?.get(n)) {
+            let bb = format!(
+                "{},{}",
+                self.point((b.x, b.bottom())),
+                self.point((b.right(), b.y))
+            );
+            attributes.insert("bb".into(), bb.into());
+        }
+        attributes
+    }
+
+    pub fn edge(&self, edge_id: EdgeId) -> Attributes<'a> {
+        let attributes = self.dot.edge_attributes.get(edge_id);
+        self.edge_with_layout(attributes, self.layout.map(|l| l.edges.get(edge_id)))
+    }
+
+    /// `index` is the place in [`DirectedGraph::iter_self_edges`].
+    pub fn self_edge(&self, index: usize) -> Attributes<'a> {
+        let attributes = &self.dot.self_edge_attributes[index];
+        self.edge_with_layout(attributes, self.layout.map(|l| &l.self_edges[index]))
+    }
+
+    fn edge_with_layout(
+        &self,
+        attributes: &Attributes<'a>,
+        edge: Option<&EdgeLayout>,
+    ) -> Attributes<'a> {
+        let mut attributes = attributes.clone();
+        if let Some(edge) = edge {
+            let mut pos = edge
+                .arrow
+                .map_or(String::new(), |tip| format!("e,{} ", self.point(tip)));
+            let points: Vec<_> = edge.points.iter().map(|&p| self.point(p)).collect();
+            pos += &points.join(" ");
+            attributes.insert("pos".into(), pos.into());
+            for (name, point) in [
+                ("lp", edge.label),
+                ("head_lp", edge.head_label),
+                ("tail_lp", edge.tail_label),
+                ("xlp", edge.xlabel),
+            ] {
+                if let Some(point) = point {
+                    attributes.insert(name.into(), self.point(point).into());
+                }
+            }
+        }
+        attributes
+    }
+}
+
+/// Writes the graph back to DOT with all parsed attributes. Defaults are
+/// already applied to every node and edge, so no `node` and `edge` statements
+/// are written. With a layout, nodes get `pos`, `width` and `height`, edges
+/// get spline `pos`, the graph and clusters get `bb` like in `dot -Tdot`
+/// output.
+pub fn write_dot<W: Write>(
+    dot: &DotGraph,
+    layout: Option<&Layout>,
+    mut write: W,
+) -> io::Result<()> {
+    let graph = &dot.graph;
+    let output = OutputAttributes::new(dot, layout);
+
+    if dot.strict {
+        write!(write, "strict ")?;
+    }
+    write!(write, "{}", if dot.directed { "digraph" } else { "graph" })?;
+    if let Some(name) = &dot.name {
+        write!(write, " {}", quote(name))?;
+    }
+    writeln!(write, " {{")?;
+
+    let attributes = output.graph();
+    if !attributes.is_empty() {
+        writeln!(write, "\tgraph {};", attribute_list(&attributes))?;
+    }
+
+    for (id, node) in graph.iter_nodes_with_id() {
+        if node.is_virtual {
+            continue;
+        }
+        write!(write, "\t{}", quote(graph.original_id(id).unwrap()))?;
+        write_attributes(&output.node(id), &mut write)?;
+    }
+
+    let mut cluster_index = 0;
+    for subgraph in &dot.subgraphs {
+        write_subgraph(&output, subgraph, &mut cluster_index, 1, &mut write)?;
+    }
+
+    let op = if dot.directed { "->" } else { "--" };
+    for edge_id in dot.edge_attributes.iter_ids() {
+        let Edge { from, to, .. } = graph.edge(edge_id);
+        write!(
+            write,
+            "\t{} {op} {}",
+            quote(graph.original_id(*from).unwrap()),
+            quote(graph.original_id(*to).unwrap())
+        )?;
+        write_attributes(&output.edge(edge_id), &mut write)?;
+    }
+    for (index, &id) in graph.iter_self_edges().enumerate() {
+        let id = quote(graph.original_id(id).unwrap());
+        write!(write, "\t{id} {op} {id}")?;
+        write_attributes(&output.self_edge(index), &mut write)?;
+    }
+    writeln!(write, "}}")
+}
+
+/// Subgraphs are written after all nodes and only list their node ids.
+/// Anonymous edge operands without attributes are already expanded into the
+/// edges, only their nested subgraphs are written.
+fn write_subgraph<W: Write>(
+    output: &OutputAttributes,
+    subgraph: &Subgraph,
+    cluster_index: &mut usize,
+    depth: usize,
+    write: &mut W,
+) -> io::Result<()> {
+    if subgraph.edge_operand && subgraph.name.is_none() && subgraph.attributes.is_empty() {
+        for child in &subgraph.subgraphs {
+            write_subgraph(output, child, cluster_index, depth, write)?;
+        }
+        return Ok(());
+    }
+    let indent = "\t".repeat(depth);
+    write!(write, "{indent}subgraph")?;
+    if let Some(name) = &subgraph.name {
+        write!(write, " {}", quote(name))?;
+    }
+    writeln!(write, " {{")?;
+
+    let cluster = subgraph.is_cluster().then(|| {
+        *cluster_index += 1;
+        *cluster_index - 1
+    });
+    let attributes = output.subgraph(subgraph, cluster);
+    if !attributes.is_empty() {
+        writeln!(write, "{indent}\tgraph {};", attribute_list(&attributes))?;
+    }
+    for &id in &subgraph.nodes {
+        let id = output.dot.graph.original_id(id).unwrap();
+        writeln!(write, "{indent}\t{};", quote(id))?;
+    }
+    for child in &subgraph.subgraphs {
+        write_subgraph(output, child, cluster_index, depth + 1, write)?;
+    }
+    writeln!(write, "{indent}}}")
+}
+
+fn write_attributes<W: Write>(attributes: &Attributes, write: &mut W) -> io::Result<()> {
+    if !attributes.is_empty() {
+        write!(write, " {}", attribute_list(attributes))?;
+    }
+    writeln!(write, ";")
+}
+
+fn attribute_list(attributes: &Attributes) -> String {
+    let items: Vec<_> = attributes
+        .iter()
+        .map(|(name, value)| format!("{}={}", quote(name), quote(value)))
+        .collect();
+    format!("[{}]", items.join(", "))
+}
+
+/// Quotes ids which are not valid DOT names or numerals, keywords included.
+pub(crate) fn quote(id: &str) -> Cow<'_, str> {
+    let is_name = id.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || !c.is_ascii());
+    let is_keyword = ["node", "edge", "graph", "digraph", "subgraph", "strict"]
+        .iter()
+        .any(|k| k.eq_ignore_ascii_case(id));
+    if (is_name || is_numeral(id)) && !is_keyword {
+        Cow::Borrowed(id)
+    } else {
+        Cow::Owned(format!("\"{}\"", id.replace('"', "\\\"")))
+    }
+}
+
+fn is_numeral(id: &str) -> bool {
+    let digits = id.strip_prefix('-').unwrap_or(id);
+    let (int, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let all_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    all_digits(int) && all_digits(fraction) && !(int.is_empty() && fraction.is_empty())
+}
+
+/// Up to two decimal places without trailing zeros, enough for points.
+pub(crate) fn number(value: f64) -> String {
+    decimal(value, 2)
+}
+
+pub(crate) fn decimal(value: f64, precision: usize) -> String {
+    let result = format!("{value:.precision$}");
+    let result = match result.contains('.') {
+        true => result.trim_end_matches('0').trim_end_matches('.'),
+        false => &result,
+    };
+    if result == "-0" {
+        "0".into()
+    } else {
+        result.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{layout::LayoutOptions, read_dot::parse};
+
+    fn write(dot: &DotGraph, layout: Option<&Layout>) -> String {
+        let mut output = vec![];
+        write_dot(dot, layout, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let input = "strict digraph \"my graph\" {
+            label=\"a \\\"b\\\"\"; node [shape=box];
+            a -> \"b c\" [color=red]; \"b c\" -> -1.5; a -> a [label=loop];
+            subgraph cluster_x { rank=same; a; subgraph { node -> \"b c\" } }
+            { \"b c\" }
+        }";
+        let dot = parse(input).unwrap();
+        let output = write(&dot, None);
+        assert_eq!(
+            output,
+            "strict digraph \"my graph\" {
+\tgraph [label=\"a \\\"b\\\"\"];
+\ta [shape=box];
+\t\"b c\" [shape=box];
+\t-1.5 [shape=box];
+\t\"node\" [shape=box];
+\tsubgraph cluster_x {
+\t\tgraph [rank=same];
+\t\ta;
+\t\t\"node\";
+\t\t\"b c\";
+\t\tsubgraph {
+\t\t\t\"node\";
+\t\t\t\"b c\";
+\t\t}
+\t}
+\tsubgraph {
+\t\t\"b c\";
+\t}
+\ta -> \"b c\" [color=red];
+\t\"b c\" -> -1.5;
+\t\"node\" -> \"b c\";
+\ta -> a [label=loop];
+}
+"
+        );
+        let reparsed = parse(&output).unwrap();
+        assert_eq!(write(&reparsed, None), output);
+    }
+
+    #[test]
+    fn edge_operands_round_trip() {
+        let count = |dot: &DotGraph| {
+            fn count(subgraphs: &[Subgraph]) -> usize {
+                subgraphs.iter().map(|s| 1 + count(&s.subgraphs)).sum()
+            }
+            count(&dot.subgraphs)
+        };
+        let input = "digraph { a -> {b c}; {b c} -> d; {rank=same; c d} -> e;
+            a -> { subgraph cluster_x { e } } }";
+        let dot = parse(input).unwrap();
+        let output = write(&dot, None);
+        let reparsed = parse(&output).unwrap();
+        assert_eq!(count(&reparsed), 2);
+        assert_eq!(reparsed.graph.edges_count(), dot.graph.edges_count());
+        let output = write(&reparsed, None);
+        assert_eq!(count(&parse(&output).unwrap()), count(&reparsed));
+    }
+
+    #[test]
+    fn layout() {
+        let dot = parse("digraph { a -> c; a -> b; b -> c; c -> a }").unwrap();
+        let layout = crate::layout(&dot, &LayoutOptions::default());
+        let output = write(&dot, Some(&layout));
+        assert!(output.contains("graph [bb=\"0,0,108.24,208\"];"));
+        assert!(output.contains("a [height=0.5, pos=\"70,190\", width=0.56];"));
+        assert!(output.contains("b [height=0.5, pos=\"20,104\", width=0.56];"));
+        assert!(output.contains(
+            "a -> b [pos=\"e,24.66,121.5 65.34,172.5 55.8,154.41 34.66,140.47 25.13,122.39\"];"
+        ));
+        assert!(output.contains("a -> c [pos=\"e,70,36 70,172 70,126.67 70,82.33 70,37\"];"));
+        // The inverted edge is written in the input direction and goes
+        // around the straight one.
+        assert!(output.contains(
+            "c -> a [pos=\"e,70.81,172.01 73.2,35.77 78.94,52.69 90.79,68.48 95,86 \
+             108.24,141.09 76.46,114.97 70.91,171.02\"];"
+        ));
+        let reparsed = parse(&output).unwrap();
+        assert_eq!(reparsed.graph.nodes_count(), 3);
+    }
+
+    #[test]
+    fn numbers_and_ids() {
+        assert_eq!(number(1.0), "1");
+        assert_eq!(number(0.555), "0.56");
+        assert_eq!(number(-0.001), "0");
+        assert_eq!(quote("a_1"), "a_1");
+        assert_eq!(quote("1a"), "\"1a\"");
+        assert_eq!(quote("-.5"), "-.5");
+        assert_eq!(quote("1."), "1.");
+        assert_eq!(quote("."), "\".\"");
+        assert_eq!(quote("Graph"), "\"Graph\"");
+        assert_eq!(quote("привет"), "привет");
+        assert_eq!(quote(""), "\"\"");
+    }
+}
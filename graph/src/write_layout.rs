@@ -0,0 +1,317 @@
+use std::io::{self, Write};
+
+use crate::graph::*;
+use crate::layout::Layout;
+use crate::multi_edge;
+use crate::read_dot::{Attributes, DotGraph, Subgraph};
+use crate::write_dot::{decimal, quote, OutputAttributes};
+
+/// Writes Graphviz `plain` output, `plain-ext` if `extended` is true which
+/// adds ports to edge ends. Sizes and coordinates are in inches with the
+/// origin at the bottom left corner.
+pub fn write_plain<W: Write>(
+    dot: &DotGraph,
+    layout: &Layout,
+    extended: bool,
+    mut write: W,
+) -> io::Result<()> {
+    let graph = &dot.graph;
+    let b = layout.bounding_box;
+    let point = |(x, y): (f64, f64)| format!("{} {}", inches(x), inches(b.bottom() - y));
+    writeln!(write, "graph 1 {} {}", inches(b.width), inches(b.height))?;
+
+    for (id, node) in graph.iter_nodes_with_id() {
+        if node.is_virtual {
+            continue;
+        }
+        let attributes = dot.node_attributes.get(id);
+        let name = graph.original_id(id).unwrap();
+        let rect = layout.nodes.get(id);
+        writeln!(
+            write,
+            "node {} {} {} {} {} {} {} {} {}",
+            quote(name),
+            point(rect.center()),
+            inches(rect.width),
+            inches(rect.height),
+            quote(dot.label(id).unwrap_or(name)),
+            quote(value(attributes, "style", "solid")),
+            quote(value(attributes, "shape", "ellipse")),
+            quote(value(attributes, "color", "black")),
+            quote(value(attributes, "fillcolor", "lightgrey")),
+        )?;
+    }
+
+    let edges = dot.edge_attributes.iter().map(|(edge_id, attributes)| {
+        let edge = graph.edge(edge_id);
+        (edge.from, edge.to, attributes, layout.edges.get(edge_id))
+    });
+    let self_edges = graph
+        .iter_self_edges()
+        .zip(&dot.self_edge_attributes)
+        .zip(&layout.self_edges)
+        .map(|((&id, attributes), edge_layout)| (id, id, attributes, edge_layout));
+    for (from, to, attributes, edge_layout) in edges.chain(self_edges) {
+        let end = |id: NodeId, port: &str| {
+            let name = quote(graph.original_id(id).unwrap()).into_owned();
+            match attributes.get(port).filter(|_| extended) {
+                Some(port) => format!("{name}:{}", quote(port)),
+                None => name,
+            }
+        };
+        let points: Vec<_> = edge_layout.points.iter().map(|&p| point(p)).collect();
+        let text = multi_edge::edge_label(attributes, edge_layout.count);
+        let label = match (text, edge_layout.label) {
+            (Some(label), Some(center)) => format!("{} {} ", quote(&label), point(center)),
+            _ => String::new(),
+        };
+        writeln!(
+            write,
+            "edge {} {} {} {} {}{} {}",
+            end(from, "tailport"),
+            end(to, "headport"),
+            points.len(),
+            points.join(" "),
+            label,
+            quote(value(attributes, "style", "solid")),
+            quote(value(attributes, "color", "black")),
+        )?;
+    }
+    writeln!(write, "stop")
+}
+
+/// Writes Graphviz `json0` output: subgraphs and nodes are `objects`
+/// numbered by `_gvid`, subgraphs first, all attributes are strings.
+pub fn write_json<W: Write>(dot: &DotGraph, layout: &Layout, mut write: W) -> io::Result<()> {
+    let graph = &dot.graph;
+    let output = OutputAttributes::new(dot, Some(layout));
+
+    let mut subgraphs = vec![];
+    let mut cluster_index = 0;
+    for subgraph in &dot.subgraphs {
+        flatten_subgraph(&output, subgraph, &mut cluster_index, &mut subgraphs);
+    }
+    let mut node_gvids = graph.node_map();
+    let mut nodes = vec![];
+    for (id, node) in graph.iter_nodes_with_id() {
+        if !node.is_virtual {
+            node_gvids.set(id, subgraphs.len() + nodes.len());
+            nodes.push(id);
+        }
+    }
+
+    let mut fields = vec![
+        field("name", &string(dot.name.as_deref().unwrap_or(""))),
+        field("directed", &dot.directed.to_string()),
+        field("strict", &dot.strict.to_string()),
+    ];
+    fields.extend(attribute_fields(&output.graph()));
+    fields.push(field("_subgraph_cnt", &subgraphs.len().to_string()));
+
+    let mut objects = vec![];
+    for (gvid, subgraph) in subgraphs.iter().enumerate() {
+        let mut object = vec![
+            field("_gvid", &gvid.to_string()),
+            field("name", &string(&subgraph.name)),
+        ];
+        object.extend(attribute_fields(&subgraph.attributes));
+        let nodes: Vec<_> = subgraph
+            .nodes
+            .iter()
+            .map(|&id| node_gvids.get(id).to_string())
+            .collect();
+        object.push(field("nodes", &array(&nodes, 3)));
+        if !subgraph.subgraphs.is_empty() {
+            let children: Vec<_> = subgraph.subgraphs.iter().map(|n| n.to_string()).collect();
+            object.push(field("subgraphs", &array(&children, 3)));
+        }
+        objects.push(json_object(&object, 2));
+    }
+    for &id in &nodes {
+        let mut object = vec![
+            field("_gvid", &node_gvids.get(id).to_string()),
+            field("name", &string(graph.original_id(id).unwrap())),
+        ];
+        object.extend(attribute_fields(&output.node(id)));
+        objects.push(json_object(&object, 2));
+    }
+    fields.push(field("objects", &array(&objects, 1)));
+
+    let mut edges = vec![];
+    for edge_id in dot.edge_attributes.iter_ids() {
+        let Edge { from, to, .. } = graph.edge(edge_id);
+        let mut object = vec![
+            field("_gvid", &edges.len().to_string()),
+            field("tail", &node_gvids.get(*from).to_string()),
+            field("head", &node_gvids.get(*to).to_string()),
+        ];
+        object.extend(attribute_fields(&output.edge(edge_id)));
+        edges.push(json_object(&object, 2));
+    }
+    for (index, &id) in graph.iter_self_edges().enumerate() {
+        let mut object = vec![
+            field("_gvid", &edges.len().to_string()),
+            field("tail", &node_gvids.get(id).to_string()),
+            field("head", &node_gvids.get(id).to_string()),
+        ];
+        object.extend(attribute_fields(&output.self_edge(index)));
+        edges.push(json_object(&object, 2));
+    }
+    fields.push(field("edges", &array(&edges, 1)));
+
+    writeln!(write, "{}", json_object(&fields, 0))
+}
+
+/// Subgraph with its layout attributes, `subgraphs` are indexes of children in
+/// the flattened list.
+struct FlatSubgraph<'a> {
+    name: String,
+    attributes: Attributes<'a>,
+    nodes: Vec<NodeId>,
+    subgraphs: Vec<usize>,
+}
+
+/// Adds the subgraph and its children in depth first order, anonymous
+/// subgraphs are named by their number.
+fn flatten_subgraph<'a>(
+    output: &OutputAttributes<'_, 'a>,
+    subgraph: &Subgraph<'a>,
+    cluster_index: &mut usize,
+    result: &mut Vec<FlatSubgraph<'a>>,
+) -> usize {
+    let index = result.len();
+    let cluster = subgraph.is_cluster().then(|| {
+        *cluster_index += 1;
+        *cluster_index - 1
+    });
+    result.push(FlatSubgraph {
+        name: subgraph
+            .name
+            .as_ref()
+            .map_or_else(|| format!("%{index}"), |n| n.to_string()),
+        attributes: output.subgraph(subgraph, cluster),
+        nodes: subgraph.nodes.clone(),
+        subgraphs: vec![],
+    });
+    for child in &subgraph.subgraphs {
+        let child = flatten_subgraph(output, child, cluster_index, result);
+        result[index].subgraphs.push(child);
+    }
+    index
+}
+
+fn value<'v>(attributes: &'v Attributes, name: &str, default: &'v str) -> &'v str {
+    attributes.get(name).map_or(default, |v| v.as_ref())
+}
+
+fn inches(points: f64) -> String {
+    decimal(points / 72., 4)
+}
+
+fn attribute_fields(attributes: &Attributes) -> Vec<String> {
+    attributes
+        .iter()
+        .map(|(name, value)| field(name, &string(value)))
+        .collect()
+}
+
+fn field(name: &str, value: &str) -> String {
+    format!("{}: {value}", string(name))
+}
+
+fn json_object(fields: &[String], depth: usize) -> String {
+    let indent = "  ".repeat(depth);
+    format!(
+        "{{\n{indent}  {}\n{indent}}}",
+        fields.join(&format!(",\n{indent}  "))
+    )
+}
+
+fn array(items: &[String], depth: usize) -> String {
+    if items.is_empty() {
+        return "[]".into();
+    }
+    let indent = "  ".repeat(depth);
+    format!(
+        "[\n{indent}  {}\n{indent}]",
+        items.join(&format!(",\n{indent}  "))
+    )
+}
+
+fn string(value: &str) -> String {
+    let mut result = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => result += "\\\"",
+            '\\' => result += "\\\\",
+            '\n' => result += "\\n",
+            '\r' => result += "\\r",
+            '\t' => result += "\\t",
+            c if (c as u32) < 0x20 => result += &format!("\\u{:04x}", c as u32),
+            c => result.push(c),
+        }
+    }
+    result + "\""
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{layout::LayoutOptions, read_dot::parse};
+
+    fn with_layout(input: &str, output: impl FnOnce(&DotGraph, &Layout)) {
+        let dot = parse(input).unwrap();
+        let layout = crate::layout(&dot, &LayoutOptions::default());
+        output(&dot, &layout);
+    }
+
+    #[test]
+    fn plain() {
+        let input = "digraph { a -> b:p [color=red]; a -> c; \
+                     b [shape=record, label=\"<p> x|y\"]; c [label=\"C C\"] }";
+        with_layout(input, |dot, layout| {
+            let mut output = vec![];
+            write_plain(dot, layout, false, &mut output).unwrap();
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                "graph 1 1.5278 1.6944
+node a 0.7917 1.4444 0.5556 0.5 a solid ellipse black lightgrey
+node b 1.2083 0.25 0.6389 0.3472 \"<p> x|y\" solid record black lightgrey
+node c 0.375 0.25 0.75 0.5 \"C C\" solid ellipse black lightgrey
+edge a b 4 0.8289 1.1967 0.9001 0.9388 1.0486 0.7043 1.0486 0.4375 solid red
+edge a c 4 0.7479 1.1976 0.668 0.9594 0.5039 0.7495 0.4236 0.5114 solid black
+stop
+"
+            );
+            let mut output = vec![];
+            write_plain(dot, layout, true, &mut output).unwrap();
+            assert!(String::from_utf8(output).unwrap().contains("edge a b:p 4"));
+        });
+    }
+
+    #[test]
+    fn json() {
+        let input = "digraph G { subgraph cluster_x { a; { b } } a -> b [label=\"x \\\"y\\\"\"] }";
+        with_layout(input, |dot, layout| {
+            let mut output = vec![];
+            write_json(dot, layout, &mut output).unwrap();
+            let output = String::from_utf8(output).unwrap();
+            assert!(output.starts_with(
+                "{\n  \"name\": \"G\",\n  \"directed\": true,\n  \"strict\": false,\n  \
+                 \"bb\": \"0,0,77.85,150.8\",\n  \"_subgraph_cnt\": 2,\n  \"objects\": [\n    {\n      \
+                 \"_gvid\": 0,\n      \"name\": \"cluster_x\",\n      \"bb\": "
+            ));
+            assert!(output.contains("\"name\": \"%1\",\n      \"nodes\": [\n        3\n      ]"));
+            assert!(output.contains("\"subgraphs\": [\n        1\n      ]"));
+            assert!(output.contains(
+                "\"_gvid\": 2,\n      \"name\": \"a\",\n      \"height\": \"0.5\",\n      \
+                 \"pos\": \"38.92,126.8\",\n      \"width\": \"0.56\""
+            ));
+            assert!(output.contains(
+                "\"tail\": 2,\n      \"head\": 3,\n      \"label\": \"x \\\"y\\\"\",\n      \
+                 \"lp\": \"57.39,75.4\",\n      \
+                 \"pos\": \"e,38.92,42 38.92,108.8 38.92,86.53 38.92,65.27 38.92,43\""
+            ));
+        });
+    }
+}
@@ -1,18 +1,30 @@
-use crate::{graph::*, ns::network_simplex};
-use std::fmt::Debug;
+use crate::{cluster::Clusters, graph::*, layout::LayoutOptions, ns::network_simplex};
+use std::{collections::BTreeMap, fmt::Debug};
 
+/// Space between a cluster border and its nodes or nested clusters in points.
+pub const CLUSTER_MARGIN: u32 = 5;
+
+/// X coordinates of node centers, `sizes` are node widths and heights in
+/// points, `loops` is the space taken by self loops on the right of nodes.
 pub fn x_coordinates<T: Debug>(
     graph: &DirectedGraph<T>,
     ranks: &NodeMap<i32>,
     places: &NodeMap<u32>,
+    clusters: &Clusters,
+    sizes: &NodeMap<(f64, f64)>,
+    loops: &NodeMap<f64>,
+    options: &LayoutOptions,
 ) -> NodeMap<u32> {
-    let node_width = 50;
+    // Distance between centers of neighbor nodes.
+    let separation = |a: NodeId, b: NodeId| {
+        ((sizes.get(a).0 + sizes.get(b).0) / 2. + loops.get(a) + options.nodesep).round() as u32
+    };
+    // Distances from a node center to the cluster borders without the margin.
+    let half_width = |id: NodeId| ((sizes.get(id).0 + options.nodesep) / 2.).round() as u32;
+    let right_half_width = |id: NodeId| half_width(id) + loops.get(id).round() as u32;
     let mut temp_graph = DirectedGraph::<()>::new(&[], &[]);
     debug!("x_coord: Input graph has {} nodes", graph.nodes_count());
 
-    // Each edge will be replaced by node and two edges.
-    let mut left_right_ranks = NodeMap::new(graph.nodes_count() + graph.edges_count());
-
     //TODO: pass layers
     let mut layers = vec![];
     for (id, _) in graph.iter_nodes_with_id() {
@@ -32,26 +44,97 @@ pub fn x_coordinates<T: Debug>(
         temp_graph.add_node(Node::virt()); //TODO: rewrite to real node
     }
 
+    // Left and right borders of every non empty cluster.
+    let mut borders = BTreeMap::new();
+    for id in graph.iter_nodes_ids() {
+        for cluster in clusters.path(id) {
+            borders.entry(cluster).or_insert_with(|| {
+                (
+                    temp_graph.add_node(Node::virt()),
+                    temp_graph.add_node(Node::virt()),
+                )
+            });
+        }
+    }
+
     // Links nodes on the same level.
     for layer in &layers {
-        let mut iter = layer.iter().filter_map(|v| *v).enumerate().peekable();
-        while let Some((index, id)) = iter.next() {
-            left_right_ranks.set(id, index as i32 * node_width as i32);
-            if let Some(&(_, next)) = iter.peek() {
-                let edge_id = temp_graph.add_edge(Edge {
-                    from: id,
-                    to: next,
-                    kind: EdgeKind::Normal,
-                    min_length: node_width, // TODO: use real node width
-                    weight: 0,
-                });
-                temp_graph.node_mut(id).outputs.push(edge_id);
-                temp_graph.node_mut(next).inputs.push(edge_id);
+        let mut iter = layer.iter().filter_map(|v| *v).peekable();
+        while let Some(id) = iter.next() {
+            if let Some(&next) = iter.peek() {
+                add_constraint(&mut temp_graph, id, next, separation(id, next));
+            }
+        }
+    }
+
+    // Keeps cluster nodes inside of borders and clusters apart from the rest.
+    let margin = CLUSTER_MARGIN;
+    // Labels of horizontal layouts are on the left of the cluster contents.
+    let left_margin = |cluster: usize| {
+        let label = clusters.clusters[cluster].label_height();
+        margin
+            + if options.rankdir.is_horizontal() {
+                label.ceil() as u32
+            } else {
+                0
+            }
+    };
+    for (&cluster, &(left, right)) in &borders {
+        if let Some(parent) = clusters.clusters[cluster].parent {
+            let (parent_left, parent_right) = borders[&parent];
+            add_constraint(&mut temp_graph, parent_left, left, left_margin(parent));
+            add_constraint(&mut temp_graph, right, parent_right, margin);
+        }
+    }
+    for id in graph.iter_nodes_ids() {
+        if let Some(cluster) = clusters.cluster(id) {
+            let (left, right) = borders[&cluster];
+            add_constraint(
+                &mut temp_graph,
+                left,
+                id,
+                half_width(id) + left_margin(cluster),
+            );
+            add_constraint(&mut temp_graph, id, right, right_half_width(id) + margin);
+        }
+    }
+    for layer in layers.iter().filter(|_| !borders.is_empty()) {
+        let mut iter = layer.iter().filter_map(|v| *v).peekable();
+        while let Some(id) = iter.next() {
+            let Some(&next) = iter.peek() else {
+                break;
+            };
+            let (path, next_path) = (clusters.path(id), clusters.path(next));
+            let common = path
+                .iter()
+                .zip(&next_path)
+                .take_while(|(a, b)| a == b)
+                .count();
+            match (path.get(common), next_path.get(common)) {
+                (Some(a), Some(b)) => {
+                    add_constraint(&mut temp_graph, borders[a].1, borders[b].0, margin)
+                }
+                (Some(a), None) => add_constraint(
+                    &mut temp_graph,
+                    borders[a].1,
+                    next,
+                    half_width(next) + margin,
+                ),
+                (None, Some(b)) => add_constraint(
+                    &mut temp_graph,
+                    id,
+                    borders[b].0,
+                    right_half_width(id) + margin,
+                ),
+                (None, None) => {}
             }
         }
     }
 
-    // Create auxiliary nodes and edges to replace input edges.
+    let mut left_right_ranks = longest_path(&temp_graph);
+
+    // Create auxiliary nodes and edges to replace input edges, each edge will
+    // be replaced by node and two edges.
     for edge in graph.iter_edges() {
         let temp_node_id = temp_graph.add_node(Node::virt());
         debug!(
@@ -66,6 +149,7 @@ pub fn x_coordinates<T: Debug>(
             kind: EdgeKind::Normal,
             min_length: 1,
             weight: edge.weight,
+            constraint: true,
         });
         temp_graph.node_mut(temp_node_id).outputs.push(edge_id);
         temp_graph.node_mut(edge.from).inputs.push(edge_id);
@@ -76,6 +160,7 @@ pub fn x_coordinates<T: Debug>(
             kind: EdgeKind::Normal,
             min_length: 1,
             weight: edge.weight,
+            constraint: true,
         });
         temp_graph.node_mut(temp_node_id).outputs.push(edge_id);
         temp_graph.node_mut(edge.to).inputs.push(edge_id);
@@ -116,10 +201,61 @@ pub fn x_coordinates<T: Debug>(
     result
 }
 
+fn add_constraint(graph: &mut DirectedGraph<()>, from: NodeId, to: NodeId, min_length: u32) {
+    let edge_id = graph.add_edge(Edge {
+        from,
+        to,
+        kind: EdgeKind::Normal,
+        min_length,
+        weight: 0,
+        constraint: true,
+    });
+    graph.node_mut(from).outputs.push(edge_id);
+    graph.node_mut(to).inputs.push(edge_id);
+}
+
+/// Initial feasible coordinates, each node is placed at the minimal distance
+/// from the left.
+fn longest_path(graph: &DirectedGraph<()>) -> NodeMap<i32> {
+    let mut positions = graph.node_map();
+    let mut inputs_left = graph.node_map();
+    for (id, node) in graph.iter_nodes_with_id() {
+        inputs_left.set(id, node.inputs.len());
+    }
+    let mut stack: Vec<_> = graph
+        .iter_nodes_with_id()
+        .filter(|(_, n)| n.inputs.is_empty())
+        .map(|(id, _)| id)
+        .collect();
+    while let Some(id) = stack.pop() {
+        for &edge_id in &graph.node(id).outputs {
+            let edge = graph.edge(edge_id);
+            let position = *positions.get(id) + edge.min_length as i32;
+            if *positions.get(edge.to) < position {
+                positions.set(edge.to, position);
+            }
+            let left = inputs_left.get_mut(edge.to);
+            *left -= 1;
+            if *left == 0 {
+                stack.push(edge.to);
+            }
+        }
+    }
+    positions
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn sizes(count: u32) -> NodeMap<(f64, f64)> {
+        let mut sizes = NodeMap::new(count);
+        for (_, size) in sizes.iter_mut() {
+            *size = (40., 20.);
+        }
+        sizes
+    }
+
     #[test]
     fn simple() {
         let graph = DirectedGraph::new(&[0, 1, 2], &[(0, 1), (0, 2)]);
@@ -128,7 +264,15 @@ mod tests {
         ranks.set(NodeId::from(2u32), 1);
         let mut places = graph.node_map();
         places.set(NodeId::from(2u32), 1);
-        let xs = x_coordinates(&graph, &ranks, &places);
+        let xs = x_coordinates(
+            &graph,
+            &ranks,
+            &places,
+            &Clusters::default(),
+            &sizes(4),
+            &NodeMap::new(4),
+            &LayoutOptions::default(),
+        );
         assert_eq!(*xs.get(NodeId::from(0u32)), 25);
         assert_eq!(*xs.get(NodeId::from(1u32)), 0);
         assert_eq!(*xs.get(NodeId::from(2u32)), 50);
@@ -144,10 +288,93 @@ mod tests {
         let mut places = graph.node_map();
         places.set(NodeId::from(2u32), 1);
         places.set(NodeId::from(3u32), 2);
-        let xs = x_coordinates(&graph, &ranks, &places);
+        let xs = x_coordinates(
+            &graph,
+            &ranks,
+            &places,
+            &Clusters::default(),
+            &sizes(4),
+            &NodeMap::new(4),
+            &LayoutOptions::default(),
+        );
         assert_eq!(*xs.get(NodeId::from(0u32)), 50);
         assert_eq!(*xs.get(NodeId::from(1u32)), 0);
         assert_eq!(*xs.get(NodeId::from(2u32)), 50);
         assert_eq!(*xs.get(NodeId::from(3u32)), 100);
     }
+
+    #[test]
+    fn cluster() {
+        let dot = crate::read_dot::parse(
+            "digraph { a -> b; a -> c; a -> d; subgraph cluster_x { b; c } }",
+        )
+        .unwrap();
+        let mut ranks = dot.graph.node_map();
+        ranks.set(NodeId::from(1u32), 1);
+        ranks.set(NodeId::from(2u32), 1);
+        ranks.set(NodeId::from(3u32), 1);
+        let mut places = dot.graph.node_map();
+        places.set(NodeId::from(2u32), 1);
+        places.set(NodeId::from(3u32), 2);
+        let clusters = Clusters::new(&dot);
+        let xs = x_coordinates(
+            &dot.graph,
+            &ranks,
+            &places,
+            &clusters,
+            &sizes(4),
+            &NodeMap::new(4),
+            &LayoutOptions::default(),
+        );
+        assert_eq!(
+            *xs.get(NodeId::from(2u32)) - *xs.get(NodeId::from(1u32)),
+            50
+        );
+        assert_eq!(
+            *xs.get(NodeId::from(3u32)) - *xs.get(NodeId::from(2u32)),
+            60
+        );
+    }
+
+    #[test]
+    fn widths() {
+        let graph = DirectedGraph::new(&[0, 1, 2], &[(0, 1), (0, 2)]);
+        let mut ranks = graph.node_map();
+        ranks.set(NodeId::from(1u32), 1);
+        ranks.set(NodeId::from(2u32), 1);
+        let mut places = graph.node_map();
+        places.set(NodeId::from(2u32), 1);
+        let mut sizes = sizes(3);
+        sizes.set(NodeId::from(1u32), (100., 20.));
+        let xs = x_coordinates(
+            &graph,
+            &ranks,
+            &places,
+            &Clusters::default(),
+            &sizes,
+            &NodeMap::new(3),
+            &LayoutOptions::default(),
+        );
+        assert_eq!(
+            *xs.get(NodeId::from(2u32)) - *xs.get(NodeId::from(1u32)),
+            50 + 30
+        );
+
+        // Self loops take space on the right.
+        let mut loops = NodeMap::new(3);
+        loops.set(NodeId::from(1u32), 16.);
+        let xs = x_coordinates(
+            &graph,
+            &ranks,
+            &places,
+            &Clusters::default(),
+            &sizes,
+            &loops,
+            &LayoutOptions::default(),
+        );
+        assert_eq!(
+            *xs.get(NodeId::from(2u32)) - *xs.get(NodeId::from(1u32)),
+            50 + 30 + 16
+        );
+    }
 }
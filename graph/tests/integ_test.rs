@@ -1,5 +1,12 @@
-use graph::{read_dot, to_dag};
-use std::{fs::File, io::Read, str};
+use graph::{
+    cluster::Clusters, layout::LayoutOptions, port::Ports, rank_constraints, read_dot, to_dag,
+    OutputFormat,
+};
+use std::{
+    fs::File,
+    io::{Read, Write},
+    str,
+};
 extern crate difference;
 
 #[test]
@@ -17,29 +24,88 @@ fn itest_aim_simp2() -> Result<(), std::io::Error> {
     check("../dot_files/aim_simp2.dot", "tests/results/aim_simp2.dot")
 }
 
-fn check(input_name: &str, output_name: &str) -> Result<(), std::io::Error> {
+#[test]
+fn itest_cluster() -> Result<(), std::io::Error> {
+    check("../dot_files/cluster.dot", "tests/results/cluster.dot")
+}
+
+#[test]
+fn itest_layout_aim() -> Result<(), std::io::Error> {
+    check_layout("../dot_files/aim.dot", "tests/results/aim_layout.dot")
+}
+
+#[test]
+fn itest_layout_test() -> Result<(), std::io::Error> {
+    check_layout("../dot_files/test.dot", "tests/results/test_layout.dot")
+}
+
+#[test]
+fn itest_layout_aim_simp2() -> Result<(), std::io::Error> {
+    check_layout(
+        "../dot_files/aim_simp2.dot",
+        "tests/results/aim_simp2_layout.dot",
+    )
+}
+
+#[test]
+fn itest_layout_cluster() -> Result<(), std::io::Error> {
+    check_layout(
+        "../dot_files/cluster.dot",
+        "tests/results/cluster_layout.dot",
+    )
+}
+
+fn read_input(input_name: &str) -> Result<String, std::io::Error> {
     let mut data = String::new();
     File::open(input_name)
         .expect("input file")
         .read_to_string(&mut data)?;
+    Ok(data)
+}
+
+/// Checks ranks and x coordinates of the intermediate layout stages.
+fn check(input_name: &str, output_name: &str) -> Result<(), std::io::Error> {
+    let data = read_input(input_name)?;
 
     let mut dot = read_dot::parse(&data).expect("parse error");
     to_dag::to_dag(&mut dot.graph);
-    let mut ranks = graph::rank_with_components(&dot.graph);
+    let constraints = rank_constraints::rank_constraints(&dot.subgraphs);
+    let mut ranks = rank_constraints::rank(&mut dot.graph, &constraints);
+    let edges_count = dot.graph.edges_count();
+    let mut clusters = Clusters::new(&dot);
+    let mut ports = Ports::new(&dot, Default::default());
     graph::add_virtual_nodes::add_virtual_nodes(&mut dot.graph, &mut ranks);
-    let places = graph::place::places3(&dot.graph, &ranks);
-    let coords = graph::xcoord::x_coordinates(&dot.graph, &ranks, &places);
-    println!("OK!!!");
+    clusters.add_virtual_nodes(&dot.graph, edges_count);
+    ports.add_virtual_nodes(&dot.graph, edges_count);
+    let options = LayoutOptions::default();
+    let places = graph::place::places3(&dot.graph, &ranks, &clusters, &ports, &options);
+    let sizes = graph::layout::node_sizes(&dot, &options);
+    let loops = graph::self_loop::loop_widths(&dot, options.rankdir);
+    let coords = graph::xcoord::x_coordinates(
+        &dot.graph, &ranks, &places, &clusters, &sizes, &loops, &options,
+    );
 
-    if std::env::var("GS_UPDATE_TEST_RESULTS").is_ok() {
-        dot.graph.dot_result(
-            File::create(output_name).expect("output file"),
-            &ranks,
-            &coords,
-        )
-    }
     let mut output = vec![];
     dot.graph.dot_result(&mut output, &ranks, &coords);
+    compare(output, output_name)
+}
+
+/// Checks the DOT output of the whole layout.
+fn check_layout(input_name: &str, output_name: &str) -> Result<(), std::io::Error> {
+    let data = read_input(input_name)?;
+
+    let dot = read_dot::parse(&data).expect("parse error");
+    let options = LayoutOptions::from_attributes(&dot.attributes);
+    let output = graph::full_write(dot, OutputFormat::Dot, &options);
+    compare(output, output_name)
+}
+
+fn compare(output: Vec<u8>, output_name: &str) -> Result<(), std::io::Error> {
+    if std::env::var("GS_UPDATE_TEST_RESULTS").is_ok() {
+        File::create(output_name)
+            .expect("output file")
+            .write_all(&output)?;
+    }
 
     let mut expected = Vec::new();
     File::open(output_name)
@@ -74,7 +140,13 @@ fn _compare_graphs() {
     to_dag::to_dag(&mut dot.graph);
     let mut ranks = graph::rank_with_components(&dot.graph);
     graph::add_virtual_nodes::add_virtual_nodes(&mut dot.graph, &mut ranks);
-    let _places = graph::place::places3(&dot.graph, &ranks);
+    let _places = graph::place::places3(
+        &dot.graph,
+        &ranks,
+        &Clusters::default(),
+        &Ports::default(),
+        &LayoutOptions::default(),
+    );
     let old = &dot.graph; // Change this to compare different graphs
     let new = &dot.graph;
 
@@ -38,6 +38,12 @@ function init() {
         output.innerHTML = context.render(currentId, max_nodes.value, max_edges.value);
         if (!context.is_error()) {
             svgPanZoom(output.childNodes[0], { controlIconsEnabled: true, zoomScaleSensitivity: .5, maxZoom: 20 });
+        } else {
+            const errors = context.errors();
+            if (errors.length != 0) {
+                input.focus();
+                input.setSelectionRange(errors[0].start(), Math.max(errors[0].end(), errors[0].start() + 1));
+            }
         }
     }
 